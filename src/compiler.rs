@@ -1,7 +1,14 @@
+pub mod bibliography;
 pub mod callback;
 pub mod counter;
+pub mod draft;
+pub mod glossary;
 pub mod html_parser;
+pub mod listing;
+pub mod manifest;
 pub mod parser;
+pub mod people;
+pub mod query;
 pub mod section;
 pub mod state;
 pub mod taxon;
@@ -11,11 +18,13 @@ pub mod writer;
 use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
 
 use eyre::{bail, eyre, WrapErr};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use manifest::{Manifest, SourceRecord};
 use parser::parse_markdown;
 use section::{HTMLContent, ShallowSection};
 use typst::parse_typst;
 use walkdir::WalkDir;
-use writer::Writer;
+use writer::{PageNumbers, Writer};
 
 use crate::{
     config::{self, verify_and_file_hash},
@@ -23,14 +32,26 @@ use crate::{
 };
 
 pub fn compile_all(workspace_dir: &str) -> eyre::Result<()> {
+    config::clear_records();
+    if config::verify_cache_fingerprint()? {
+        println!("Cache discarded: the configuration or kodama version has changed.");
+    }
+
     let workspace = all_source_files(Path::new(workspace_dir))?;
     let mut shallows = HashMap::new();
 
+    let previous = Manifest::load();
+    let mut manifest = Manifest::default();
+
     for (slug, ext) in &workspace.slug_exts {
         let relative_path = format!("{}.{}", slug, ext);
+        let produced_start = config::produced_len();
+        let depended_start = config::depended_len();
 
+        let record = previous.source(&relative_path);
         let is_modified = verify_and_file_hash(&relative_path)
-            .wrap_err_with(|| eyre!("failed to verify hash of `{relative_path}`"))?;
+            .wrap_err_with(|| eyre!("failed to verify hash of `{relative_path}`"))?
+            || record.is_some_and(|record| record.is_dependency_modified());
 
         let entry_path_str = format!("{}.entry", relative_path);
         let entry_path_buf = config::entry_path(&entry_path_str);
@@ -49,6 +70,11 @@ pub fn compile_all(workspace_dir: &str) -> eyre::Result<()> {
                         entry_path_buf.display()
                     )
                 })?;
+
+            // The files produced when the source was parsed.
+            let record = record.map(SourceRecord::cached).unwrap_or_default();
+            manifest.insert_source(&relative_path, record);
+
             shallow
         } else {
             let shallow = match ext {
@@ -62,22 +88,53 @@ pub fn compile_all(workspace_dir: &str) -> eyre::Result<()> {
                 eyre!("failed to write entry to `{}`", entry_path_buf.display())
             })?;
 
+            let record = SourceRecord::new(
+                config::produced_since(produced_start),
+                config::depended_since(depended_start),
+            );
+            manifest.insert_source(&relative_path, record);
+
             shallow
         };
 
         shallows.insert(slug.to_string(), shallow);
     }
 
-    let state = state::compile_all(shallows)?;
+    let unpublished = draft::take_unpublished(&mut shallows)?;
 
-    Writer::write_needed_slugs(
-        &workspace.slug_exts.into_iter().map(|x| x.0).collect(),
-        &state,
-    );
+    let references = bibliography::reference_shallows(&shallows)?;
+    let reference_slugs: Vec<String> = references.keys().cloned().collect();
+    shallows.extend(references);
+
+    let state = state::compile_all(shallows, unpublished)?;
+
+    // The pages are written one by one, to record the files of each in its source.
+    let mut page_numbers = PageNumbers::new();
+    for (slug, ext) in &workspace.slug_exts {
+        if state.is_unpublished(slug) {
+            continue;
+        }
+        let produced_start = config::produced_len();
+        page_numbers.extend(Writer::write_needed_slugs(&[slug.to_string()], &state));
+        let relative_path = format!("{}.{}", slug, ext);
+        manifest.insert_pages(&relative_path, config::produced_since(produced_start));
+    }
+    page_numbers.extend(Writer::write_needed_slugs(&reference_slugs, &state));
+    Writer::write_listings(&listing::tag_listings(&state)?, &state);
+    Writer::write_listings(&listing::taxon_listings(&state, &page_numbers), &state);
+    Writer::write_listings(&listing::recent_listings(&state), &state);
+    Writer::write_listings(&listing::timeline_listings(&state), &state);
+
+    manifest.finish(config::produced_since(0));
+    manifest.prune(&previous);
+    manifest.save()?;
 
     Ok(())
 }
 
+/// Ignore rules in gitignore syntax, in addition to `ignore` in `kodama.toml`.
+pub const IGNORE_FILE: &str = ".kodamaignore";
+
 pub fn should_ignored_file(path: &Path) -> bool {
     let name = path.file_name().unwrap();
     name == "README.md"
@@ -89,10 +146,36 @@ pub fn should_ignored_dir(path: &Path) -> bool {
         .map_or(false, |s| s.starts_with('.') || s.starts_with('_'))
 }
 
+/// The rules of [`IGNORE_FILE`] in the project root and `ignore` in `kodama.toml`.
+fn ignore_rules(root_dir: &Path) -> eyre::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root_dir);
+    let ignore_file = root_dir.join(IGNORE_FILE);
+    if ignore_file.exists() {
+        if let Some(err) = builder.add(&ignore_file) {
+            return Err(err).wrap_err_with(|| eyre!("failed to read `{}`", ignore_file.display()));
+        }
+    }
+    for line in &config::project_config().ignore {
+        builder
+            .add_line(None, line)
+            .wrap_err_with(|| eyre!("invalid rule `{line}` in `ignore`"))?;
+    }
+    builder.build().wrap_err("failed to build ignore rules")
+}
+
 /**
  * collect all source file paths in workspace dir
  */
 pub fn all_source_files(root_dir: &Path) -> eyre::Result<Workspace> {
+    let rules = ignore_rules(root_dir)?;
+    let is_ignored = |p: &Path| {
+        let relative = p.strip_prefix(root_dir).unwrap_or(p);
+        match p.is_dir() {
+            true => should_ignored_dir(p) || rules.matched(relative, true).is_ignore(),
+            false => should_ignored_file(p) || rules.matched(relative, false).is_ignore(),
+        }
+    };
+
     let mut slug_exts = HashMap::new();
     let to_slug_ext = |p: &Path| {
         let p = p.strip_prefix(root_dir).unwrap_or(p);
@@ -110,21 +193,21 @@ pub fn all_source_files(root_dir: &Path) -> eyre::Result<Workspace> {
     };
     for entry in std::fs::read_dir(root_dir).wrap_err_with(|| failed_to_read_dir(root_dir))? {
         let path = entry.wrap_err_with(|| failed_to_read_dir(root_dir))?.path();
-        if path.is_file() && !should_ignored_file(&path) {
+        if is_ignored(&path) {
+            continue;
+        }
+        if path.is_file() {
             let Some((slug, ext)) = to_slug_ext(&path) else {
                 continue;
             };
             if let Some(ext) = slug_exts.insert(slug, ext) {
                 bail!(file_collide(&path, ext));
             };
-        } else if path.is_dir() && !should_ignored_dir(&path) {
+        } else if path.is_dir() {
             for entry in WalkDir::new(&path)
                 .follow_links(true)
                 .into_iter()
-                .filter_entry(|e| {
-                    let path = e.path();
-                    path.is_file() || !should_ignored_dir(path)
-                })
+                .filter_entry(|e| !is_ignored(e.path()))
             {
                 let path = entry
                     .wrap_err_with(|| failed_to_read_dir(&path))?
@@ -0,0 +1,208 @@
+use std::collections::{BTreeSet, HashMap};
+
+use biblatex::{Bibliography, ChunksExt, Entry};
+use eyre::{eyre, WrapErr};
+use htmlize::escape_text;
+
+use crate::{
+    config,
+    entry::{HTMLMetaData, KEY_PAGE_TITLE, KEY_SLUG, KEY_TAXON, KEY_TITLE},
+    html,
+    process::embed_markdown::display_taxon,
+};
+
+use super::section::{HTMLContent, LazyContent, ShallowSection};
+
+pub const BIB_DIR: &str = "bib";
+
+/// Slug of the generated reference entry of the citation key.
+pub fn bib_slug(key: &str) -> String {
+    let key: String = key
+        .trim()
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '-' || c == '_' {
+            true => c,
+            false => '-',
+        })
+        .collect();
+    format!("{}/{}", BIB_DIR, key)
+}
+
+/// Load the bibliography files listed by `bibliography` in `kodama.toml`.
+fn load() -> eyre::Result<Bibliography> {
+    let mut bibliography = Bibliography::new();
+    for path in &config::project_config().bibliography {
+        let full_path = config::join_path(&config::root_dir(), path);
+        let source = std::fs::read_to_string(&full_path)
+            .wrap_err_with(|| eyre!("failed to read bibliography `{full_path}`"))?;
+        let entries = Bibliography::parse(&source)
+            .map_err(|err| eyre!("{err}"))
+            .wrap_err_with(|| eyre!("failed to parse bibliography `{full_path}`"))?;
+        for entry in entries {
+            bibliography.insert(entry);
+        }
+    }
+    Ok(bibliography)
+}
+
+/// Generate a reference entry for each cited key in the bibliography,
+/// keyed by the slug, e.g. `bib/knuth1984`.
+pub fn reference_shallows(
+    shallows: &HashMap<String, ShallowSection>,
+) -> eyre::Result<HashMap<String, ShallowSection>> {
+    let prefix = format!("{}/", BIB_DIR);
+    let mut cited: BTreeSet<String> = BTreeSet::new();
+    for shallow in shallows.values() {
+        if let HTMLContent::Lazy(contents) = &shallow.content {
+            for content in contents {
+                match content {
+                    LazyContent::Local(local) if local.slug.starts_with(&prefix) => {
+                        cited.insert(local.slug.to_string());
+                    }
+                    LazyContent::Citation(slugs) => cited.extend(slugs.iter().cloned()),
+                    _ => (),
+                }
+            }
+        }
+    }
+    if cited.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let bibliography = load()?;
+    let entries: HashMap<String, &Entry> = bibliography
+        .iter()
+        .map(|entry| (bib_slug(&entry.key), entry))
+        .collect();
+
+    let mut references = HashMap::new();
+    for slug in cited {
+        if shallows.contains_key(&slug) {
+            continue;
+        }
+        match entries.get(&slug) {
+            Some(entry) => {
+                references.insert(slug.to_string(), reference_shallow(&slug, entry));
+            }
+            None => eprintln!("Error: citation [{}] not found in the bibliography.", slug),
+        }
+    }
+    Ok(references)
+}
+
+fn reference_shallow(slug: &str, entry: &Entry) -> ShallowSection {
+    let plain = |s: String| HTMLContent::Plain(s);
+    let mut metadata = HashMap::new();
+    metadata.insert(KEY_SLUG.to_string(), plain(slug.to_string()));
+    metadata.insert(KEY_TAXON.to_string(), plain(display_taxon("reference")));
+    metadata.insert(KEY_PAGE_TITLE.to_string(), plain(citation_label(entry)));
+    if let Some(title) = field(entry, "title") {
+        metadata.insert(KEY_TITLE.to_string(), plain(escape_text(title).to_string()));
+    }
+    let authors = authors(entry);
+    if !authors.is_empty() {
+        metadata.insert(
+            "author".to_string(),
+            plain(escape_text(authors.join(", ")).to_string()),
+        );
+    }
+    if let Some(year) = year(entry) {
+        metadata.insert("date".to_string(), plain(year));
+    }
+
+    let bibtex = entry
+        .to_bibtex_string()
+        .unwrap_or_else(|_| entry.to_biblatex_string());
+    let content = format!(
+        "{}{}",
+        html!(p class = "bibliography" => (format_entry(entry))),
+        html!(pre class = "bibtex" => (escape_text(bibtex)))
+    );
+
+    ShallowSection {
+        metadata: HTMLMetaData(metadata),
+        content: HTMLContent::Plain(content),
+    }
+}
+
+fn field(entry: &Entry, key: &str) -> Option<String> {
+    entry
+        .get(key)
+        .map(|chunks| chunks.format_verbatim())
+        .filter(|s| !s.trim().is_empty())
+}
+
+fn authors(entry: &Entry) -> Vec<String> {
+    entry
+        .author()
+        .map(|persons| persons.iter().map(|p| p.to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn year(entry: &Entry) -> Option<String> {
+    field(entry, "year").or_else(|| field(entry, "date").map(|date| date.chars().take(4).collect()))
+}
+
+/// The author-year label of the entry, e.g. `Knuth 1984` or `Knuth et al. 1984`.
+fn citation_label(entry: &Entry) -> String {
+    let names: Vec<String> = entry
+        .author()
+        .map(|persons| persons.into_iter().map(|p| p.name).collect())
+        .unwrap_or_default();
+    let names = match names.as_slice() {
+        [] => entry.key.to_string(),
+        [name] => name.to_string(),
+        [a, b] => format!("{} and {}", a, b),
+        [a, ..] => format!("{} et al.", a),
+    };
+    match year(entry) {
+        Some(year) => format!("{} {}", names, year),
+        None => names,
+    }
+}
+
+/// Format the entry as `Authors. Title. Container, volume(number), pages. Publisher, year.`
+fn format_entry(entry: &Entry) -> String {
+    let text = |key: &str| field(entry, key).map(|s| escape_text(s).to_string());
+
+    let mut container = text("journaltitle")
+        .or_else(|| text("journal"))
+        .or_else(|| text("booktitle"))
+        .map(|s| format!("<em>{}</em>", s))
+        .into_iter()
+        .collect::<Vec<_>>();
+    if let Some(volume) = text("volume") {
+        let number = text("number")
+            .map(|n| format!("({})", n))
+            .unwrap_or_default();
+        container.push(format!("{}{}", volume, number));
+    }
+    if let Some(pages) = text("pages") {
+        container.push(format!("pp. {}", pages.replace("--", "–")));
+    }
+
+    let publication = [text("publisher").or_else(|| text("school")), year(entry)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    let link = text("doi")
+        .map(|doi| format!("https://doi.org/{}", doi))
+        .or_else(|| text("url"))
+        .map(|url| html!(a href = {&url} => (url)));
+
+    let authors = escape_text(authors(entry).join(", ")).to_string();
+    [
+        Some(authors),
+        text("title"),
+        Some(container.join(", ")),
+        Some(publication.join(", ")),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|s| !s.is_empty())
+    .map(|s| format!("{}.", s.trim_end_matches('.')))
+    .chain(link)
+    .collect::<Vec<_>>()
+    .join(" ")
+}
@@ -1,41 +1,149 @@
+use std::collections::HashMap;
+
+use crate::config::{self, NumberingStyle};
 
 #[derive(Debug, Clone)]
 pub struct Counter {
-    pub numbers: Vec<u8>,
+    /// Numbers of the enclosing numbered section.
+    pub prefix: Vec<usize>,
+
+    /// The last number of each named counter under the prefix.
+    pub counters: HashMap<String, usize>,
+
+    /// The first number of each counter.
+    pub start: usize,
 }
 
 impl Counter {
     pub fn init() -> Self {
-        return Counter { numbers: vec![0] };
+        Counter::starting_at(1)
+    }
+
+    pub fn starting_at(start: usize) -> Self {
+        Counter {
+            prefix: vec![],
+            counters: HashMap::new(),
+            start,
+        }
+    }
+
+    /// Step the counter `name`, and return the numbers of the stepped one.
+    pub fn step_mut(&mut self, name: &str) -> Vec<usize> {
+        let start = self.start;
+        let number = self
+            .counters
+            .entry(name.to_string())
+            .and_modify(|n| *n += 1)
+            .or_insert(start);
+
+        let mut numbers = self.prefix.clone();
+        numbers.push(*number);
+        numbers
+    }
+
+    /// Counter of the children of the section numbered by `numbers`.
+    pub fn left_shift_by(numbers: Vec<usize>) -> Counter {
+        Counter {
+            prefix: numbers,
+            ..Counter::init()
+        }
     }
 
-    pub fn display(&self) -> String {
-        self.numbers
+    pub fn display(numbers: &[usize]) -> String {
+        let numbering = &config::project_config().numbering;
+        let text = numbers
             .iter()
-            .map(|n| format!("{}.", n))
-            .reduce(|s: String, t| s + &t)
-            .unwrap()
+            .map(|n| format_number(*n, numbering.style))
+            .collect::<Vec<_>>()
+            .join(".");
+        match numbering.trailing_dot {
+            true => format!("{}.", text),
+            false => text,
+        }
+    }
+}
+
+fn format_number(n: usize, style: NumberingStyle) -> String {
+    match style {
+        NumberingStyle::Arabic => n.to_string(),
+        NumberingStyle::Roman => to_roman(n).to_lowercase(),
+        NumberingStyle::UpperRoman => to_roman(n),
+        NumberingStyle::Alpha => to_alpha(n).to_lowercase(),
+        NumberingStyle::UpperAlpha => to_alpha(n),
     }
+}
 
-    pub fn step_at_mut(&mut self, level: usize) {
-        let len = self.numbers.len();
-        let index = len - level;
-        if index < len {
-            self.numbers[index] += 1;
+fn to_roman(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    if n == 0 {
+        return n.to_string();
+    }
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            roman.push_str(numeral);
+            n -= value;
         }
     }
+    roman
+}
 
-    pub fn step_mut(&mut self) {
-        self.step_at_mut(1)
+/// `1, 2, ..., 26, 27` to `A, B, ..., Z, AA`.
+fn to_alpha(mut n: usize) -> String {
+    if n == 0 {
+        return n.to_string();
+    }
+    let mut letters = vec![];
+    while n > 0 {
+        n -= 1;
+        letters.push((b'A' + (n % 26) as u8) as char);
+        n /= 26;
     }
+    letters.iter().rev().collect()
+}
 
-    pub fn left_shift_by(&self, n: u8) -> Counter {
-        let mut counter = self.clone();
-        counter.numbers.push(n);
-        return counter;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_roman_numbers() {
+        let romans: Vec<String> = [1, 4, 9, 14, 40, 90, 400, 1994, 2024]
+            .into_iter()
+            .map(to_roman)
+            .collect();
+        assert_eq!(
+            romans,
+            ["I", "IV", "IX", "XIV", "XL", "XC", "CD", "MCMXCIV", "MMXXIV"]
+        );
+        assert_eq!(format_number(12, NumberingStyle::Roman), "xii");
+        assert_eq!(format_number(12, NumberingStyle::UpperRoman), "XII");
+        assert_eq!(to_roman(0), "0");
     }
 
-    pub fn left_shift(&self) -> Counter {
-        self.left_shift_by(0)
+    #[test]
+    fn format_alpha_numbers() {
+        let alphas: Vec<String> = [1, 2, 26, 27, 28, 52, 53, 702, 703]
+            .into_iter()
+            .map(to_alpha)
+            .collect();
+        assert_eq!(alphas, ["A", "B", "Z", "AA", "AB", "AZ", "BA", "ZZ", "AAA"]);
+        assert_eq!(format_number(3, NumberingStyle::Alpha), "c");
+        assert_eq!(format_number(3, NumberingStyle::UpperAlpha), "C");
+        assert_eq!(to_alpha(0), "0");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use eyre::{eyre, WrapErr};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::{
+    config,
+    date::Date,
+    entry::{MetaData, KEY_DRAFT, KEY_PUBLISH_AFTER},
+};
+
+use super::section::ShallowSection;
+
+/// Whether the entry is a draft, is scheduled after today, or is private.
+fn is_unpublished(slug: &str, shallow: &ShallowSection, private: &GlobSet, today: &Date) -> bool {
+    let metadata = &shallow.metadata;
+    if metadata.get_bool(KEY_DRAFT).unwrap_or(false) || private.is_match(slug) {
+        return true;
+    }
+
+    let Some(publish_after) = metadata.get(KEY_PUBLISH_AFTER) else {
+        return false;
+    };
+    match Date::parse(&publish_after.remove_all_tags()) {
+        Ok(date) => {
+            let day = Date {
+                time: None,
+                zone: None,
+                ..date
+            };
+            day > *today
+        }
+        Err(err) => {
+            eprintln!("Error: [{}] {} in `{}`.", slug, err, KEY_PUBLISH_AFTER);
+            true
+        }
+    }
+}
+
+fn private_globs() -> eyre::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &config::project_config().private {
+        let glob =
+            Glob::new(pattern).wrap_err_with(|| eyre!("invalid glob `{pattern}` in `private`"))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// Remove the unpublished entries unless `--drafts` is given, and return
+/// the titles of the removed entries by their slugs.
+pub fn take_unpublished(
+    shallows: &mut HashMap<String, ShallowSection>,
+) -> eyre::Result<HashMap<String, String>> {
+    if config::is_drafts() {
+        return Ok(HashMap::new());
+    }
+
+    let private = private_globs()?;
+    let today = Date::today();
+    let slugs: Vec<String> = shallows
+        .iter()
+        .filter(|(slug, shallow)| is_unpublished(slug, shallow, &private, &today))
+        .map(|(slug, _)| slug.to_string())
+        .collect();
+
+    Ok(slugs
+        .into_iter()
+        .map(|slug| {
+            let shallow = shallows.remove(&slug).unwrap();
+            let title = shallow
+                .metadata
+                .title()
+                .map(|title| title.remove_all_tags())
+                .unwrap_or_else(|| slug.to_string());
+            (slug, title)
+        })
+        .collect())
+}
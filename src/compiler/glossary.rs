@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet};
+
+use regex_lite::Regex;
+
+use crate::{
+    config,
+    entry::{parse_list, MetaData, KEY_ALIASES, KEY_AUTOLINK, KEY_TERMS},
+    html,
+    recorder::{ParseRecorder, State},
+};
+
+use super::section::{HTMLContent, HTMLContentBuilder, LazyContent, LocalLink, ShallowSection};
+
+/// Elements whose text is never linked.
+const SKIPPED_TAGS: [&str; 12] = [
+    "a", "code", "pre", "script", "style", "svg", "h1", "h2", "h3", "h4", "h5", "h6",
+];
+
+/// Terms declared by the `terms` and `aliases` metadata of the entries.
+pub struct Glossary {
+    /// Term folded by [`fold_case`] => slug of the defining entry.
+    terms: HashMap<String, String>,
+
+    /// Matches any term in the folded text, longer terms first.
+    regex: Option<Regex>,
+}
+
+impl Glossary {
+    pub fn new(shallows: &HashMap<String, ShallowSection>) -> Glossary {
+        let mut slugs: Vec<&String> = shallows.keys().collect();
+        slugs.sort();
+
+        let mut terms: HashMap<String, String> = HashMap::new();
+        for slug in slugs {
+            let metadata = &shallows[slug].metadata;
+            let mut entry_terms: Vec<String> = [KEY_TERMS, KEY_ALIASES]
+                .iter()
+                .filter_map(|key| metadata.get(key))
+                .flat_map(|value| parse_list(&value.remove_all_tags()))
+                .collect();
+            if entry_terms.is_empty() {
+                continue;
+            }
+            entry_terms.extend(metadata.page_title().cloned());
+
+            for term in entry_terms {
+                let term = fold_case(term.trim());
+                match terms.get(&term) {
+                    Some(defined) if defined != slug => eprintln!(
+                        "Error: [{}] term `{}` is already defined by [{}].",
+                        slug, term, defined
+                    ),
+                    _ => {
+                        terms.insert(term, slug.to_string());
+                    }
+                }
+            }
+        }
+
+        let mut sorted: Vec<&String> = terms.keys().collect();
+        sorted.sort_by_key(|term| std::cmp::Reverse(term.chars().count()));
+        let regex = (!sorted.is_empty()).then(|| {
+            let alternation: Vec<String> = sorted.iter().map(|t| regex_lite::escape(t)).collect();
+            Regex::new(&alternation.join("|")).unwrap()
+        });
+
+        Glossary { terms, regex }
+    }
+
+    fn is_enabled(shallow: &ShallowSection) -> bool {
+        shallow
+            .metadata
+            .get_bool(KEY_AUTOLINK)
+            .unwrap_or(config::project_config().glossary.autolink)
+    }
+
+    /// Link the first occurrence of the terms of each entry in the content,
+    /// except for the entries which are already linked.
+    pub fn autolink(&self, shallow: &mut ShallowSection) {
+        if self.regex.is_none() || !Glossary::is_enabled(shallow) {
+            return;
+        }
+
+        let contents = match &shallow.content {
+            HTMLContent::Plain(html) => vec![LazyContent::Plain(html.to_string())],
+            HTMLContent::Lazy(contents) => contents.clone(),
+        };
+
+        let mut linked: HashSet<String> = HashSet::from([shallow.slug()]);
+        for content in &contents {
+            if let LazyContent::Local(local) = content {
+                linked.insert(local.slug.to_string());
+            }
+        }
+
+        let mut builder = HTMLContentBuilder::new();
+        let mut changed = false;
+        for content in contents {
+            match content {
+                LazyContent::Plain(html) => {
+                    changed |= self.link_terms(&html, &mut linked, &mut builder);
+                }
+                content => builder.push(content),
+            }
+        }
+        if changed {
+            shallow.content = builder.build();
+        }
+    }
+
+    /// Push the `html` to the builder with the terms in text linked,
+    /// and return whether any term has been linked.
+    fn link_terms(
+        &self,
+        html: &str,
+        linked: &mut HashSet<String>,
+        builder: &mut HTMLContentBuilder,
+    ) -> bool {
+        // Tags, comments and entities. The math is in `nolink` spans, see [`isolate_math`].
+        static RE_TOKEN: std::sync::LazyLock<Regex> = std::sync::LazyLock::new(|| {
+            Regex::new(concat!(
+                r#"<(?<close>/)?(?<name>[a-zA-Z][a-zA-Z0-9]*)[^>]*>|<!--[\s\S]*?-->"#,
+                r#"|&(?:#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z][a-zA-Z0-9]*);"#,
+            ))
+            .unwrap()
+        });
+
+        let mut changed = false;
+        let mut skipped = 0;
+        let mut spans: Vec<bool> = vec![];
+        let mut cursor = 0;
+
+        for token in RE_TOKEN.captures_iter(html) {
+            let all = token.get(0).unwrap();
+            let text = &html[cursor..all.start()];
+            if skipped == 0 {
+                changed |= self.link_text(text, linked, builder);
+            } else {
+                builder.push_str(text);
+            }
+            builder.push_str(all.as_str());
+            cursor = all.end();
+
+            let Some(name) = token.name("name").map(|s| s.as_str().to_lowercase()) else {
+                continue;
+            };
+            let is_close = token.name("close").is_some();
+            if name == "span" {
+                match is_close {
+                    false => {
+                        let is_nolink = all.as_str().contains(r#"class="nolink""#);
+                        skipped += is_nolink as usize;
+                        spans.push(is_nolink);
+                    }
+                    true => skipped -= spans.pop().unwrap_or(false) as usize,
+                }
+            } else if SKIPPED_TAGS.contains(&name.as_str()) {
+                match is_close {
+                    false => skipped += 1,
+                    true => skipped = skipped.saturating_sub(1),
+                }
+            }
+        }
+        let text = &html[cursor..];
+        if skipped == 0 {
+            changed |= self.link_text(text, linked, builder);
+        } else {
+            builder.push_str(text);
+        }
+        changed
+    }
+
+    fn link_text(
+        &self,
+        text: &str,
+        linked: &mut HashSet<String>,
+        builder: &mut HTMLContentBuilder,
+    ) -> bool {
+        let regex = self.regex.as_ref().unwrap();
+        let folded = fold_case(text);
+        let mut changed = false;
+        let mut cursor = 0;
+        let mut start = 0;
+        while let Some(matched) = regex.find_at(&folded, start) {
+            start = matched.end().max(matched.start() + 1);
+            if !is_word_boundary(text, matched.start(), matched.end()) {
+                continue;
+            }
+            let slug = &self.terms[matched.as_str()];
+            if !linked.insert(slug.to_string()) {
+                continue;
+            }
+            builder.push_str(&text[cursor..matched.start()]);
+            builder.push(LazyContent::Local(LocalLink {
+                slug: slug.to_string(),
+                text: Some(text[matched.range()].to_string()),
+            }));
+            cursor = matched.end();
+            changed = true;
+        }
+        builder.push_str(&text[cursor..]);
+        changed
+    }
+}
+
+/// Wrap the KaTeX math of an autolinked page in a `nolink` span, since its `$` delimiters
+/// cannot be told apart from a literal `$` in the HTML. Unchanged if not autolinked.
+pub fn isolate_math(math: String, recorder: &ParseRecorder) -> String {
+    match recorder.autolink {
+        true => html!(span class = {State::NoLink.strify()} => (math)),
+        false => math,
+    }
+}
+
+/// Lowercase the text without changing the byte offsets of its characters, so that
+/// the matches in the folded text are at the same ranges in the text. Unlike `(?i)`
+/// of `regex_lite`, this is not limited to ASCII, e.g. `Ähnlichkeit` or `Σ`.
+fn fold_case(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(l), None) if l.len_utf8() == c.len_utf8() => l,
+                _ => c,
+            }
+        })
+        .collect()
+}
+
+/// Whether `text[start..end]` is not a part of a longer word. CJK characters are
+/// not considered word characters, since they are written without spaces.
+fn is_word_boundary(text: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() && (c as u32) < 0x2E80;
+    let before = text[..start].chars().next_back();
+    let first = text[start..end].chars().next();
+    let last = text[start..end].chars().next_back();
+    let after = text[end..].chars().next();
+    let joined = |a: Option<char>, b: Option<char>| matches!((a, b), (Some(a), Some(b)) if is_word(a) && is_word(b));
+    !joined(before, first) && !joined(last, after)
+}
@@ -9,6 +9,7 @@ use std::sync::LazyLock;
 pub enum HTMLTagKind {
     Meta,
     Embed,
+    Query,
     Local { span: bool },
 }
 
@@ -17,6 +18,7 @@ impl HTMLTagKind {
         match name {
             "meta" => HTMLTagKind::Meta,
             "embed" => HTMLTagKind::Embed,
+            "query" => HTMLTagKind::Query,
             "local" => HTMLTagKind::Local { span },
             _ => unreachable!(),
         }
@@ -24,9 +26,9 @@ impl HTMLTagKind {
 
     fn tri_equal(&self, k: &HTMLTagKind) -> Option<bool> {
         match (self, k) {
-            (HTMLTagKind::Meta, HTMLTagKind::Meta) | (HTMLTagKind::Embed, HTMLTagKind::Embed) => {
-                Some(true)
-            }
+            (HTMLTagKind::Meta, HTMLTagKind::Meta)
+            | (HTMLTagKind::Embed, HTMLTagKind::Embed)
+            | (HTMLTagKind::Query, HTMLTagKind::Query) => Some(true),
             (HTMLTagKind::Local { span: a }, HTMLTagKind::Local { span: b }) => {
                 if a == b {
                     Some(true)
@@ -66,7 +68,7 @@ impl<'a> HTMLParser<'a> {
                 format!(r#"?<real{}>"#, alt)
             }
             fn kodama(alt: u8) -> String {
-                format!(r#"kodama(?<tag{}>meta|embed|local)"#, alt)
+                format!(r#"kodama(?<tag{}>meta|embed|query|local)"#, alt)
             }
             fn local(alt: u8) -> String {
                 format!(r#"kodama(?<tag{}>local)"#, alt)
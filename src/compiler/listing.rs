@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+
+use eyre::bail;
+
+use crate::{
+    config,
+    date::Date,
+    entry::{display_date, MetaData, KEY_DATE},
+    html, html_flake,
+    recorder::State,
+};
+
+use super::{
+    section::{heading_anchor, Section},
+    state::CompileState,
+    writer::PageNumbers,
+};
+
+pub const TAG_DIR: &str = "tag";
+pub const TAXON_DIR: &str = "taxon";
+pub const RECENT_SLUG: &str = "recent";
+pub const TIMELINE_SLUG: &str = "timeline";
+
+/// A generated page listing entries, e.g. the page of a tag.
+pub struct Listing {
+    pub slug: String,
+    pub title: String,
+    pub taxon: String,
+
+    /// HTML of the list.
+    pub content: String,
+}
+
+/// Slug of the page of the tag, or `None` if the tag has no letters or digits.
+/// Tags differing in punctuation, e.g. `C++` and `C#`, collide, see [`tag_listings`].
+pub fn tag_slug(tag: &str) -> Option<String> {
+    let anchor = heading_anchor(tag);
+    (!anchor.is_empty()).then(|| format!("{}/{}", TAG_DIR, anchor))
+}
+
+/// The tag regardless of its case and spacing, e.g. `Linear Algebra` and `linear  algebra`.
+fn fold_tag(tag: &str) -> String {
+    tag.to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn taxon_slug(data_taxon: &str) -> String {
+    format!("{}/{}", TAXON_DIR, heading_anchor(data_taxon))
+}
+
+/// Entries that are written as pages, i.e. without the compiled metadata.
+fn entries(state: &CompileState) -> impl Iterator<Item = &Section> {
+    state
+        .compiled()
+        .iter()
+        .filter(|(slug, _)| !slug.ends_with(":metadata"))
+        .map(|(_, section)| section)
+}
+
+fn entry_items<F>(mut sections: Vec<&Section>, mut context: F) -> String
+where
+    F: FnMut(&Section) -> String,
+{
+    let sort = config::project_config().sort.listings;
+    sections.sort_by(|a, b| sort.compare(&a.metadata, &b.metadata));
+    let items: Vec<String> = sections
+        .iter()
+        .map(|section| {
+            let page_title = section.metadata.page_title().map_or("", |s| s);
+            let taxon = section.metadata.taxon().map_or("", |s| s);
+            html_flake::html_query_item(&section.slug(), page_title, taxon, &context(section))
+        })
+        .collect();
+    html_flake::html_query_list(&items)
+}
+
+fn index_listing(dir: &str, title: &str, items: Vec<String>) -> Listing {
+    Listing {
+        slug: format!("{}/index", dir),
+        title: title.to_string(),
+        taxon: String::new(),
+        content: html_flake::html_query_list(&items),
+    }
+}
+
+/// A page for each tag, and an index page of all tags. The spellings of a tag differing
+/// in case or spacing are listed together, but different tags with the same slug fail
+/// the build, rather than renaming the page of either of them.
+pub fn tag_listings(state: &CompileState) -> eyre::Result<Vec<Listing>> {
+    // tag slug => (tag, sections)
+    let mut tags: BTreeMap<String, (String, Vec<&Section>)> = BTreeMap::new();
+    for section in entries(state) {
+        for tag in section.metadata.tags() {
+            let Some(slug) = tag_slug(&tag) else {
+                eprintln!(
+                    "Warning: [{}] has the tag `{}` without letters or digits, which is not listed.",
+                    section.slug(),
+                    tag,
+                );
+                continue;
+            };
+            let (listed, sections) = tags
+                .entry(slug.to_string())
+                .or_insert_with(|| (tag.clone(), vec![]));
+            if fold_tag(listed) != fold_tag(&tag) {
+                bail!(
+                    "[{}] has the tag `{}`, which collides with `{}` of [{}] as the page `{}`",
+                    section.slug(),
+                    tag,
+                    listed,
+                    sections[0].slug(),
+                    slug,
+                );
+            }
+            if *listed != tag {
+                eprintln!(
+                    "Warning: [{}] has the tag `{}`, listed together with `{}`.",
+                    section.slug(),
+                    tag,
+                    listed,
+                );
+            }
+            sections.push(section);
+        }
+    }
+    if tags.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let index_items: Vec<String> = tags
+        .iter()
+        .map(|(slug, (tag, sections))| {
+            let text = format!("{} ({})", tag, sections.len());
+            html_flake::html_query_item(slug, &text, "", "")
+        })
+        .collect();
+
+    let mut listings = vec![index_listing(TAG_DIR, "Tags", index_items)];
+    listings.extend(tags.into_iter().map(|(slug, (tag, sections))| Listing {
+        slug,
+        title: tag,
+        taxon: "Tag. ".to_string(),
+        content: entry_items(sections, |_| String::new()),
+    }));
+    Ok(listings)
+}
+
+/// The plural name of the taxon in the taxon registry.
+fn taxon_plural(data_taxon: &str) -> Option<String> {
+    let project_config = config::project_config();
+    project_config
+        .taxon(data_taxon)
+        .and_then(|(_, taxon)| taxon.plural.get(&project_config.lang))
+        .map(str::to_string)
+}
+
+/// A page for each indexed taxon, and an index page of these taxa.
+/// Each entry is listed with its parent and its numbering in the parent,
+/// as recorded by writing the parent page.
+pub fn taxon_listings(state: &CompileState, page_numbers: &PageNumbers) -> Vec<Listing> {
+    let project_config = config::project_config();
+
+    // taxon slug => (data taxon, sections)
+    let mut taxa: BTreeMap<String, (String, Vec<&Section>)> = BTreeMap::new();
+    for section in entries(state) {
+        let Some(data_taxon) = section.metadata.data_taxon() else {
+            continue;
+        };
+        if data_taxon.is_empty() || !project_config.is_taxon_indexed(data_taxon) {
+            continue;
+        }
+        taxa.entry(taxon_slug(data_taxon))
+            .or_insert_with(|| (data_taxon.to_string(), vec![]))
+            .1
+            .push(section);
+    }
+    if taxa.is_empty() {
+        return vec![];
+    }
+
+    let context = |section: &Section| {
+        let slug = section.slug();
+        let Some(parent) = state.callback().0.get(&slug).map(|c| &c.parent) else {
+            return String::new();
+        };
+        let Some(parent_section) = state.compiled().get(parent).filter(|_| *parent != slug) else {
+            return String::new();
+        };
+        let number = page_numbers
+            .get(parent)
+            .and_then(|numbers| numbers.get(&slug));
+
+        let page_title = parent_section.metadata.page_title().map_or("", |s| s);
+        let parent_link = html_flake::html_link(
+            &config::full_html_url(parent),
+            &format!("{} [{}]", page_title, parent),
+            page_title,
+            State::LocalLink.strify(),
+        );
+        match number {
+            Some(number) => format!("{} in {}", number.trim(), parent_link),
+            None => format!("in {}", parent_link),
+        }
+    };
+
+    let index_items: Vec<String> = taxa
+        .iter()
+        .map(|(slug, (data_taxon, sections))| {
+            let title = taxon_plural(data_taxon);
+            let text = format!(
+                "{} ({})",
+                title.as_deref().unwrap_or(data_taxon),
+                sections.len()
+            );
+            html_flake::html_query_item(slug, &text, "", "")
+        })
+        .collect();
+
+    let mut listings = vec![index_listing(TAXON_DIR, "Taxa", index_items)];
+    for (slug, (data_taxon, sections)) in taxa {
+        listings.push(Listing {
+            slug,
+            title: taxon_plural(&data_taxon).unwrap_or(data_taxon),
+            taxon: "Taxon. ".to_string(),
+            content: entry_items(sections, &context),
+        });
+    }
+    listings
+}
+
+/// The dated entries with their dates, the latest first.
+fn dated_entries(state: &CompileState) -> Vec<(Date, &Section)> {
+    let mut dated: Vec<(Date, &Section)> = entries(state)
+        .filter_map(|section| section.metadata.date().map(|date| (date, section)))
+        .collect();
+    dated.sort_by(|(a, a_section), (b, b_section)| {
+        b.cmp(a)
+            .then_with(|| a_section.slug().cmp(&b_section.slug()))
+    });
+    dated
+}
+
+fn dated_item(section: &Section) -> String {
+    let page_title = section.metadata.page_title().map_or("", |s| s);
+    let taxon = section.metadata.taxon().map_or("", |s| s);
+    let date = section.metadata.get_str(KEY_DATE).map_or("", |s| s);
+    html_flake::html_query_item(&section.slug(), page_title, taxon, &display_date(date))
+}
+
+/// A page of the latest dated entries, if `index.recent` is set.
+pub fn recent_listings(state: &CompileState) -> Vec<Listing> {
+    let limit = config::project_config().index.recent;
+    if limit == 0 {
+        return vec![];
+    }
+
+    let items: Vec<String> = dated_entries(state)
+        .into_iter()
+        .take(limit)
+        .map(|(_, section)| dated_item(section))
+        .collect();
+    vec![Listing {
+        slug: RECENT_SLUG.to_string(),
+        title: "Recent".to_string(),
+        taxon: String::new(),
+        content: html_flake::html_query_list(&items),
+    }]
+}
+
+/// A page of all dated entries grouped by year and month, if `index.timeline` is set.
+pub fn timeline_listings(state: &CompileState) -> Vec<Listing> {
+    if !config::project_config().index.timeline {
+        return vec![];
+    }
+
+    let mut content = String::new();
+    let mut year = None;
+    let mut month = None;
+    let mut items: Vec<String> = vec![];
+    for (date, section) in dated_entries(state) {
+        if year != Some(date.year) || month != date.month {
+            if !items.is_empty() {
+                content.push_str(&html_flake::html_query_list(&std::mem::take(&mut items)));
+            }
+            if year != Some(date.year) {
+                let id = date.year.to_string();
+                content.push_str(&html!(h2 id = {&id} => (id)));
+            }
+            if let Some(name) = date.month_name() {
+                let id = format!("{:04}-{:02}", date.year, date.month.unwrap());
+                content.push_str(&html!(h3 id = {id} => (name)));
+            }
+            year = Some(date.year);
+            month = date.month;
+        }
+        items.push(dated_item(section));
+    }
+    if !items.is_empty() {
+        content.push_str(&html_flake::html_query_list(&items));
+    }
+
+    vec![Listing {
+        slug: TIMELINE_SLUG.to_string(),
+        title: "Timeline".to_string(),
+        taxon: String::new(),
+        content,
+    }]
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, typst_deps};
+
+/// Files produced by a build, saved in `.cache/manifest.json` to remove the stale ones
+/// of the sources in the next build.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Source path => the record of parsing the source.
+    /// All paths are relative to the project root, unless the output is outside.
+    sources: BTreeMap<String, SourceRecord>,
+
+    /// All files produced by the build.
+    outputs: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceRecord {
+    /// The files produced by parsing the source.
+    pub produced: BTreeSet<String>,
+
+    /// The files written for the page of the source, i.e. its HTML and the hash of it,
+    /// and none if the source is unpublished.
+    #[serde(default)]
+    pub pages: BTreeSet<String>,
+
+    /// The Typst files and their dependencies used by the source.
+    pub dependencies: BTreeSet<String>,
+
+    /// [`typst_deps::dependencies_hash`] of the dependencies when the source was parsed.
+    pub dependencies_hash: u64,
+}
+
+impl SourceRecord {
+    pub fn new(produced: Vec<String>, dependencies: Vec<String>) -> SourceRecord {
+        let dependencies: BTreeSet<String> = dependencies.into_iter().collect();
+        SourceRecord {
+            produced: produced.into_iter().collect(),
+            pages: BTreeSet::new(),
+            dependencies_hash: typst_deps::dependencies_hash(&dependencies),
+            dependencies,
+        }
+    }
+
+    /// The record of a source which is not parsed again, before its page is written.
+    pub fn cached(&self) -> SourceRecord {
+        SourceRecord {
+            pages: BTreeSet::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether any dependency has been modified since the source was parsed.
+    pub fn is_dependency_modified(&self) -> bool {
+        !self.dependencies.is_empty()
+            && typst_deps::dependencies_hash(&self.dependencies) != self.dependencies_hash
+    }
+}
+
+impl Manifest {
+    /// The manifest of the previous build, or an empty one for the first build.
+    pub fn load() -> Manifest {
+        std::fs::read_to_string(config::manifest_path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> eyre::Result<()> {
+        let path = config::manifest_path();
+        let serialized = serde_json::to_string(self).unwrap();
+        std::fs::write(&path, serialized).wrap_err_with(|| eyre!("failed to write `{path}`"))
+    }
+
+    pub fn source(&self, source: &str) -> Option<&SourceRecord> {
+        self.sources.get(source)
+    }
+
+    pub fn insert_source(&mut self, source: &str, record: SourceRecord) {
+        self.sources.insert(source.to_string(), record);
+    }
+
+    pub fn insert_pages(&mut self, source: &str, pages: Vec<String>) {
+        if let Some(record) = self.sources.get_mut(source) {
+            record.pages.extend(pages);
+        }
+    }
+
+    /// Collect all files produced by the build, including those of the cached sources.
+    pub fn finish(&mut self, produced: Vec<String>) {
+        self.outputs = produced.into_iter().collect();
+        for record in self.sources.values() {
+            self.outputs.extend(record.produced.iter().cloned());
+            self.outputs.extend(record.pages.iter().cloned());
+        }
+    }
+
+    /// The files of the sources in the `previous` build which this build does not produce,
+    /// e.g. all files of a deleted source, in one of `dirs`. Other files, such as the
+    /// stylesheets, and the files elsewhere, e.g. after `--output` is changed, are kept.
+    fn stale_outputs(&self, previous: &Manifest, dirs: &[String]) -> BTreeSet<String> {
+        let is_inside = |path: &str| dirs.iter().any(|dir| Path::new(path).starts_with(dir));
+        previous
+            .sources
+            .values()
+            .flat_map(|record| record.produced.iter().chain(&record.pages))
+            .filter(|path| !self.outputs.contains(*path) && is_inside(path))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the [`Manifest::stale_outputs`] in the output and cache directories,
+    /// and report them.
+    pub fn prune(&self, previous: &Manifest) {
+        let root_dir = config::root_dir();
+        let dirs = [
+            config::relative_to_root(&config::join_path(&root_dir, &config::output_dir())),
+            config::relative_to_root(&config::get_cache_dir()),
+        ];
+        for path in self.stale_outputs(previous, &dirs) {
+            let full_path = Path::new(&root_dir).join(&path);
+            if !full_path.is_file() {
+                continue;
+            }
+            match std::fs::remove_file(&full_path) {
+                Ok(()) => {
+                    println!("Removed: {}", path);
+                    // Leave the non-empty directory.
+                    let _ = full_path.parent().map(std::fs::remove_dir);
+                }
+                Err(err) => eprintln!("Failed to remove `{}`: {:?}", path, err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::config::CompileConfig;
+
+    fn record(produced: &[&str], pages: &[&str]) -> SourceRecord {
+        SourceRecord {
+            produced: produced.iter().map(|s| s.to_string()).collect(),
+            pages: pages.iter().map(|s| s.to_string()).collect(),
+            ..SourceRecord::default()
+        }
+    }
+
+    #[test]
+    fn stale_outputs_of_the_previous_sources() {
+        let mut previous = Manifest::default();
+        previous.insert_source(
+            "a.md",
+            record(&[".cache/entry/a.md.entry"], &["publish/a.html"]),
+        );
+        previous.insert_source(
+            "b.md",
+            record(&[".cache/entry/b.md.entry"], &["publish/b.html"]),
+        );
+        previous.insert_source("c.md", record(&["publish/shared.svg"], &["old/c.html"]));
+        previous.finish(vec!["publish/main.css".to_string()]);
+
+        let mut current = Manifest::default();
+        current.insert_source(
+            "a.md",
+            record(&[".cache/entry/a.md.entry"], &["publish/a.html"]),
+        );
+        current.insert_source("d.md", record(&["publish/shared.svg"], &[]));
+        current.finish(vec![]);
+
+        let dirs = ["publish".to_string(), ".cache".to_string()];
+        let stale: Vec<String> = current
+            .stale_outputs(&previous, &dirs)
+            .into_iter()
+            .collect();
+        assert_eq!(stale, [".cache/entry/b.md.entry", "publish/b.html"]);
+    }
+
+    fn files(dir: &Path) -> BTreeSet<String> {
+        walkdir::WalkDir::new(dir)
+            .into_iter()
+            .map(|entry| entry.unwrap().into_path())
+            .filter(|path| path.is_file())
+            .map(|path| path.strip_prefix(dir).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prune_the_files_of_a_deleted_source() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("kodama-prune-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let write = |name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+
+        let typst = root.join("typst.sh");
+        write("typst.sh", "#!/bin/sh\necho '<svg></svg>'\n");
+        fs::set_permissions(&typst, fs::Permissions::from_mode(0o755)).unwrap();
+        write(
+            "kodama.toml",
+            &format!("[typst]\nexecutable = \"{}\"\n", typst.display()),
+        );
+        write("index.md", "---\ntitle: Index\n---\n\nIndex\n");
+        write("a.md", "---\ntitle: A\n---\n\n[](/afig.typ#:span)\n");
+        write("afig.typ", "A\n");
+        write("b.md", "---\ntitle: B\n---\n\n[](/bfig.typ#:span)\n");
+        write("bfig.typ", "B\n");
+
+        let root_dir = root.to_str().unwrap().to_string();
+        config::mutex_set(
+            &config::CONFIG,
+            CompileConfig::new(root_dir.clone(), "publish".to_string()),
+        );
+        config::load_project_config().unwrap();
+
+        let built = |root: &PathBuf| {
+            let mut built = files(&root.join("publish"))
+                .into_iter()
+                .map(|path| format!("publish/{}", path))
+                .collect::<BTreeSet<String>>();
+            built.extend(
+                files(&root.join(".cache"))
+                    .into_iter()
+                    .map(|path| format!(".cache/{}", path)),
+            );
+            built
+        };
+
+        crate::compiler::compile_all(&root_dir).unwrap();
+        let first = built(&root);
+        fs::remove_file(root.join("b.md")).unwrap();
+        crate::compiler::compile_all(&root_dir).unwrap();
+        let second = built(&root);
+
+        let removed: BTreeSet<&String> = first.difference(&second).collect();
+        let of_b: BTreeSet<&String> = first
+            .iter()
+            .filter(|path| {
+                path.contains("b.md") || path.contains("b.html") || path.contains("bfig")
+            })
+            .collect();
+        assert!(of_b.iter().any(|path| path.ends_with(".svg")));
+        assert!(of_b.iter().any(|path| path.starts_with(".cache/entry/")));
+        assert!(of_b.iter().any(|path| path.starts_with(".cache/hash/")));
+        assert!(of_b.contains(&"publish/b.html".to_string()));
+        assert_eq!(removed, of_b);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
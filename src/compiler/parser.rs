@@ -1,10 +1,13 @@
 use std::{collections::HashMap, vec};
 
 use eyre::{eyre, WrapErr};
-use pulldown_cmark::{html, CowStr, Event, Options, Tag, TagEnd};
+use pulldown_cmark::{html, BrokenLink, CowStr, Event, Options, Tag, TagEnd};
 
 use crate::{
-    config::input_path, entry::HTMLMetaData, process::processer::Processer, recorder::ParseRecorder,
+    config::input_path,
+    entry::HTMLMetaData,
+    process::{figure::resolve_figures, processer::Processer, typst_image::resolve_inline_typsts},
+    recorder::ParseRecorder,
 };
 
 use super::{
@@ -51,6 +54,7 @@ pub fn parse_markdown(slug: &str) -> eyre::Result<ShallowSection> {
         &mut processers,
         false,
     )?;
+    let contents = resolve_figures(contents, &mut recorder.figure_counter, slug);
     let metadata = HTMLMetaData(metadata);
 
     return Ok(ShallowSection {
@@ -59,11 +63,13 @@ pub fn parse_markdown(slug: &str) -> eyre::Result<ShallowSection> {
     });
 }
 
+/// Parse a metadata value of the page recorded by `page`, with its settings,
+/// see [`ParseRecorder::metadata_recorder`].
 pub fn parse_spanned_markdown(
     markdown_input: &str,
-    current_slug: &str,
+    page: &ParseRecorder,
 ) -> eyre::Result<HTMLContent> {
-    let mut recorder = ParseRecorder::new(current_slug.to_owned());
+    let mut recorder = page.metadata_recorder();
 
     let mut processers: Vec<Box<dyn Processer>> = vec![
         Box::new(crate::process::typst_image::TypstImage),
@@ -80,6 +86,14 @@ pub fn parse_spanned_markdown(
     )
 }
 
+/// Turn the citations `[@a; @b]`, which are broken links in Markdown, into links to `@a; @b`.
+fn citation_callback<'a>(link: BrokenLink<'a>) -> Option<(CowStr<'a>, CowStr<'a>)> {
+    link.reference
+        .trim()
+        .starts_with('@')
+        .then(|| (link.reference.clone(), CowStr::Borrowed("")))
+}
+
 pub fn parse_content(
     markdown_input: &str,
     recorder: &mut ParseRecorder,
@@ -88,7 +102,11 @@ pub fn parse_content(
     ignore_paragraph: bool,
 ) -> eyre::Result<HTMLContent> {
     let mut contents: LazyContents = vec![];
-    let parser = pulldown_cmark::Parser::new_ext(&markdown_input, OPTIONS);
+    let parser = pulldown_cmark::Parser::new_with_broken_link_callback(
+        markdown_input,
+        OPTIONS,
+        Some(citation_callback),
+    );
 
     for mut event in parser {
         match &event {
@@ -198,6 +216,8 @@ pub fn parse_content(
         }
     }
 
+    resolve_inline_typsts(&mut contents, recorder);
+
     if contents.len() == 1 {
         if let LazyContent::Plain(html) = &contents[0] {
             return Ok(HTMLContent::Plain(html.to_string()));
@@ -0,0 +1,108 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::entry::{parse_list, MetaData, KEY_ALIASES, KEY_AUTHOR, KEY_CONTRIBUTORS};
+
+use super::{
+    section::{HTMLContentBuilder, LazyContent, LocalLink, ShallowSection},
+    taxon::Taxon,
+};
+
+/// Person entries, i.e. the entries of a taxon with `person = true`,
+/// and the entries they authored or contributed to.
+#[derive(Debug, Default)]
+pub struct People {
+    /// Lowercase name => slug of the person entry.
+    names: HashMap<String, String>,
+
+    /// Slug of the person => slugs of the entries authored by the person.
+    authored: HashMap<String, BTreeSet<String>>,
+
+    /// Slug of the person => slugs of the entries the person contributed to.
+    contributed: HashMap<String, BTreeSet<String>>,
+}
+
+impl People {
+    /// A person entry is named by its slug, the last part of its slug,
+    /// its title and its `aliases`.
+    pub fn new(shallows: &HashMap<String, ShallowSection>) -> People {
+        let mut names = HashMap::new();
+        for (slug, shallow) in shallows {
+            let metadata = &shallow.metadata;
+            if !metadata.data_taxon().is_some_and(|s| Taxon::is_person(s)) {
+                continue;
+            }
+
+            let basename = slug.rsplit('/').next().unwrap_or(slug);
+            let aliases = metadata
+                .get(KEY_ALIASES)
+                .map(|s| parse_list(&s.remove_all_tags()))
+                .unwrap_or_default();
+            [slug.as_str(), basename]
+                .into_iter()
+                .map(str::to_string)
+                .chain(metadata.page_title().cloned())
+                .chain(aliases)
+                .for_each(|name| {
+                    names.insert(name.trim().to_lowercase(), slug.to_string());
+                });
+        }
+
+        People {
+            names,
+            ..Default::default()
+        }
+    }
+
+    /// Replace the `author` and `contributors` of the entry naming any person entry
+    /// by links to the person entries, and record the entry for the persons.
+    pub fn resolve(&mut self, shallow: &mut ShallowSection) {
+        let slug = shallow.slug();
+        for key in [KEY_AUTHOR, KEY_CONTRIBUTORS] {
+            let Some(value) = shallow.metadata.get(key) else {
+                continue;
+            };
+            let names = parse_list(&value.remove_all_tags());
+            let persons: Vec<Option<&String>> = names
+                .iter()
+                .map(|name| self.names.get(&name.to_lowercase()))
+                .collect();
+            if persons.iter().all(Option::is_none) {
+                continue;
+            }
+
+            let mut builder = HTMLContentBuilder::new();
+            for (index, (name, person)) in names.iter().zip(&persons).enumerate() {
+                if index > 0 {
+                    builder.push_str(", ");
+                }
+                match person {
+                    Some(person) => builder.push(LazyContent::Local(LocalLink {
+                        slug: person.to_string(),
+                        text: None,
+                    })),
+                    None => builder.push_str(name),
+                }
+            }
+
+            let entries = match key == KEY_AUTHOR {
+                true => &mut self.authored,
+                false => &mut self.contributed,
+            };
+            for person in persons.into_iter().flatten() {
+                entries
+                    .entry(person.to_string())
+                    .or_default()
+                    .insert(slug.to_string());
+            }
+            shallow.metadata.0.insert(key.to_string(), builder.build());
+        }
+    }
+
+    pub fn authored(&self, person: &str) -> Option<&BTreeSet<String>> {
+        self.authored.get(person)
+    }
+
+    pub fn contributed(&self, person: &str) -> Option<&BTreeSet<String>> {
+        self.contributed.get(person)
+    }
+}
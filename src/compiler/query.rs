@@ -0,0 +1,190 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use eyre::{bail, eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::entry::{HTMLMetaData, MetaData};
+
+use super::section::{heading_anchor, ShallowSection};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum QuerySort {
+    #[default]
+    Title,
+    Slug,
+    Date,
+    Updated,
+}
+
+/// A sort key prefixed by `-` for descending order, e.g. `-date`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Sort {
+    pub key: QuerySort,
+    pub descending: bool,
+}
+
+impl Sort {
+    pub fn parse(value: &str) -> eyre::Result<Sort> {
+        let key = match value.trim_start_matches('-') {
+            "title" => QuerySort::Title,
+            "slug" => QuerySort::Slug,
+            "date" => QuerySort::Date,
+            "updated" => QuerySort::Updated,
+            _ => bail!("unknown sort key `{value}`"),
+        };
+        Ok(Sort {
+            key,
+            descending: value.starts_with('-'),
+        })
+    }
+
+    /// Compare by the sort key, then by the slug. Undated entries come first.
+    pub fn compare<V: Clone, M: MetaData<V>>(&self, a: &M, b: &M) -> Ordering {
+        let slug = |m: &M| m.slug().cloned().unwrap_or_default();
+        let ordering = match self.key {
+            QuerySort::Title => a.page_title().cmp(&b.page_title()),
+            QuerySort::Slug => Ordering::Equal,
+            QuerySort::Date => a.date().cmp(&b.date()),
+            QuerySort::Updated => a.updated().cmp(&b.updated()),
+        }
+        .then_with(|| slug(a).cmp(&slug(b)));
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+impl TryFrom<String> for Sort {
+    type Error = eyre::Report;
+
+    fn try_from(value: String) -> eyre::Result<Sort> {
+        Sort::parse(&value)
+    }
+}
+
+/// Filters of a query block, e.g. `query?taxon=theorem&under=algebra&sort=title&limit=10`.
+/// All filters must be satisfied by a matching entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Query {
+    /// `taxon=a,b`: Any of the (data-)taxa, case-insensitive.
+    pub taxa: Vec<String>,
+
+    /// `tag=a,b`: Entries with any of the tags.
+    pub tags: Vec<String>,
+
+    /// `under=dir`: Entries in the directory `dir`.
+    pub under: Option<String>,
+
+    /// `meta.key=value`: Entries whose metadata `key` has the plain text `value`.
+    pub metadata: Vec<(String, String)>,
+
+    /// `sort=title|slug|date|updated`, prefixed by `-` for descending order.
+    pub sort: QuerySort,
+    pub descending: bool,
+
+    /// `limit=n`: At most `n` entries.
+    pub limit: Option<usize>,
+
+    /// `mode=list|embed`: List links to the entries, or embed them.
+    pub embed: bool,
+}
+
+impl Query {
+    /// Parse the query string after `?` in the URL.
+    pub fn parse(url: &str) -> eyre::Result<Query> {
+        let query_str = url.split_once('?').map_or(url, |(_, s)| s);
+        let mut query = Query::default();
+        for pair in query_str.split('&').filter(|s| !s.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "taxon" => query.taxa.extend(
+                    value
+                        .split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_lowercase()),
+                ),
+                "tag" => query.tags.extend(
+                    value
+                        .split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(heading_anchor),
+                ),
+                "under" => query.under = Some(value.trim_matches('/').to_string()),
+                "sort" => {
+                    let sort = Sort::parse(value).wrap_err("invalid sort in query")?;
+                    query.sort = sort.key;
+                    query.descending = sort.descending;
+                }
+                "limit" => {
+                    let limit = value
+                        .parse()
+                        .map_err(|_| eyre!("expected a number for `limit` in query"))?;
+                    query.limit = Some(limit);
+                }
+                "mode" => {
+                    query.embed = match value {
+                        "list" => false,
+                        "embed" => true,
+                        _ => bail!("unknown mode `{value}` in query"),
+                    };
+                }
+                _ => match key.strip_prefix("meta.") {
+                    Some(key) => query.metadata.push((key.to_string(), value.to_string())),
+                    None => bail!("unknown query parameter `{key}`"),
+                },
+            }
+        }
+        Ok(query)
+    }
+
+    pub fn matches(&self, metadata: &HTMLMetaData) -> bool {
+        let plain = |key: &str| metadata.get(key).map(|v| v.remove_all_tags());
+
+        let taxon_matched = self.taxa.is_empty()
+            || metadata
+                .data_taxon()
+                .is_some_and(|taxon| self.taxa.contains(&taxon.to_lowercase()));
+
+        let tag_matched = self.tags.is_empty()
+            || metadata
+                .tags()
+                .iter()
+                .any(|tag| self.tags.contains(&heading_anchor(tag)));
+
+        let under_matched = self.under.as_ref().is_none_or(|dir| {
+            let slug = metadata.slug().map_or("", |s| s);
+            dir.is_empty() || slug.starts_with(&format!("{}/", dir))
+        });
+
+        let metadata_matched = self
+            .metadata
+            .iter()
+            .all(|(key, value)| plain(key).is_some_and(|s| s.trim() == value));
+
+        taxon_matched && tag_matched && under_matched && metadata_matched
+    }
+
+    /// Return the slugs of the matching entries, except for `current`.
+    pub fn evaluate(
+        &self,
+        shallows: &HashMap<String, ShallowSection>,
+        current: &str,
+    ) -> Vec<String> {
+        let mut matched: Vec<&ShallowSection> = shallows
+            .iter()
+            .filter(|(slug, shallow)| *slug != current && self.matches(&shallow.metadata))
+            .map(|(_, shallow)| shallow)
+            .collect();
+
+        let sort = Sort {
+            key: self.sort,
+            descending: self.descending,
+        };
+        matched.sort_by(|a, b| sort.compare(&a.metadata, &b.metadata));
+
+        let limit = self.limit.unwrap_or(matched.len());
+        matched.into_iter().take(limit).map(|s| s.slug()).collect()
+    }
+}
@@ -1,34 +1,178 @@
+use eyre::{bail, eyre};
 use regex_lite::Regex;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashSet, mem, sync::LazyLock};
 
-use crate::entry::{EntryMetaData, HTMLMetaData, MetaData};
+use crate::{
+    config,
+    entry::{EntryMetaData, HTMLMetaData, MetaData},
+    html_flake,
+    recorder::State,
+};
+
+use super::query::Query;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SectionOption {
-    pub numbering: bool, // default: false
+    /// `None` follows the taxon registry, see [`SectionOption::inherit_taxon`].
+    pub numbering: Option<bool>, // default: false
 
     /// Display children catalog
-    pub details_open: bool, // default: true
+    pub details_open: Option<bool>, // default: true
 
     /// Display in catalog
     pub catalog: bool, // default: true
+
+    /// Display the header (taxon, title and metadata)
+    #[serde(default = "default_true")]
+    pub header: bool, // default: true
+
+    /// Display the metadata in header
+    #[serde(default = "default_true")]
+    pub metadata: bool, // default: true
+
+    /// Display as a compact card
+    #[serde(default)]
+    pub card: bool, // default: false
+
+    /// Display only the first N paragraphs with a "read more" link
+    #[serde(default)]
+    pub preview: Option<usize>, // default: None
+
+    /// Override the taxon of the embedded entry
+    #[serde(default)]
+    pub taxon: Option<String>, // default: None
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Default for SectionOption {
     fn default() -> Self {
-        SectionOption::new(false, true, true)
+        SectionOption {
+            numbering: None,
+            details_open: None,
+            catalog: true,
+            header: true,
+            metadata: true,
+            card: false,
+            preview: None,
+            taxon: None,
+        }
     }
 }
 
 impl SectionOption {
     pub fn new(numbering: bool, details_open: bool, catalog: bool) -> SectionOption {
         SectionOption {
-            numbering,
-            details_open,
+            numbering: Some(numbering),
+            details_open: Some(details_open),
             catalog,
+            ..SectionOption::default()
         }
     }
+
+    pub fn numbering(&self) -> bool {
+        self.numbering.unwrap_or(false)
+    }
+
+    pub fn details_open(&self) -> bool {
+        self.details_open.unwrap_or(true)
+    }
+
+    /// Fill the unspecified `numbering` and `details_open` by the defaults of the taxon.
+    pub fn inherit_taxon(&mut self, data_taxon: Option<&str>) {
+        let Some((_, taxon)) = data_taxon.and_then(|s| config::project_config().taxon(s)) else {
+            return;
+        };
+        self.numbering = self.numbering.or(taxon.numbering);
+        self.details_open = self.details_open.or(taxon.collapsed.map(|b| !b));
+    }
+
+    /// Set the option `key` by its textual `value`, where `None` means a bare key, e.g.
+    /// `numbering` or `taxon=Remark` in `{numbering, taxon=Remark}`.
+    ///
+    /// A bare boolean key is set to `true`, and `no-key` is the same as `key=false`.
+    /// The value `auto` keeps the default, which may depend on the taxon.
+    pub fn set(&mut self, key: &str, value: Option<&str>) -> eyre::Result<()> {
+        let (key, value) = match key.strip_prefix("no-") {
+            Some(key) if value.is_none() => (key, Some("false")),
+            _ => (key, value),
+        };
+        let def = SectionOption::default();
+        let bool_value = |def: bool| match value {
+            None => true,
+            Some("auto") => def,
+            Some("false") | Some("0") | Some("none") => false,
+            Some(_) => true,
+        };
+        let auto_value = || match value {
+            Some("auto") => None,
+            _ => Some(bool_value(false)),
+        };
+        let option_value = || match value {
+            None | Some("auto") | Some("none") => None,
+            Some(s) => Some(s.to_string()),
+        };
+
+        match key {
+            "numbering" => self.numbering = auto_value(),
+            "open" => self.details_open = auto_value(),
+            "collapsed" => self.details_open = auto_value().map(|b| !b),
+            "catalog" => self.catalog = bool_value(def.catalog),
+            "header" => self.header = bool_value(def.header),
+            "metadata" => self.metadata = bool_value(def.metadata),
+            "card" => self.card = bool_value(def.card),
+            "preview" => {
+                self.preview = option_value()
+                    .map(|n| n.parse())
+                    .transpose()
+                    .map_err(|_| eyre!("expected a number of paragraphs for `preview`"))?;
+            }
+            "taxon" => self.taxon = option_value(),
+            _ => bail!("unknown embed option `{key}`"),
+        }
+        Ok(())
+    }
+}
+
+/// The part of an entry to be embedded, specified by the URL fragment
+/// before the action, e.g. `/x.md#heading:lemma#:embed`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum EmbedPart {
+    #[default]
+    Whole,
+
+    /// `#header`: Title and metadata only.
+    Header,
+
+    /// `#heading:anchor`: Contents under the heading, up to the next heading
+    /// of the same or higher level.
+    Heading(String),
+
+    /// `#region:name`: Contents between `<!-- region: name -->` and `<!-- endregion -->`.
+    Region(String),
+}
+
+impl EmbedPart {
+    /// Split `url#fragment` to `(url, part)`.
+    pub fn split_url(url: &str) -> (String, EmbedPart) {
+        let Some((url, fragment)) = url.split_once('#') else {
+            return (url.to_string(), EmbedPart::Whole);
+        };
+        let part = if fragment.is_empty() {
+            EmbedPart::Whole
+        } else if fragment == "header" {
+            EmbedPart::Header
+        } else if let Some(name) = fragment.strip_prefix("region:") {
+            EmbedPart::Region(name.to_string())
+        } else {
+            let anchor = fragment.strip_prefix("heading:").unwrap_or(fragment);
+            EmbedPart::Heading(anchor.to_string())
+        };
+        (url.to_string(), part)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +180,17 @@ pub struct EmbedContent {
     pub url: String,
     pub title: Option<String>,
     pub option: SectionOption,
+
+    #[serde(default)]
+    pub part: EmbedPart,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryContent {
+    pub query: Query,
+
+    /// Options for each embedded entry in the embed mode
+    pub option: SectionOption,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +206,10 @@ pub enum LazyContent {
     Plain(String),
     Embed(EmbedContent),
     Local(LocalLink),
+    Query(QueryContent),
+
+    /// Citations `[@a; @b]` to the slugs of the generated reference entries.
+    Citation(Vec<String>),
 }
 
 pub type LazyContents = Vec<LazyContent>;
@@ -121,6 +280,8 @@ impl HTMLContent {
                             .as_ref()
                             .map(|s| remove_tag(s))
                             .unwrap_or_default(),
+
+                        LazyContent::Query(_) | LazyContent::Citation(_) => String::new(),
                     };
                     str.push_str(&s);
                 }
@@ -197,6 +358,33 @@ pub type SectionContents = Vec<SectionContent>;
 pub enum SectionContent {
     Plain(String),
     Embed(Section),
+    Local(LocalRef),
+}
+
+/// A local link resolved by the writer, which displays the numbered taxon of the
+/// target if it is numbered in the page, e.g. `Lemma 2.3`.
+#[derive(Debug, Clone)]
+pub struct LocalRef {
+    pub slug: String,
+    pub title: String,
+    pub text: Option<String>,
+}
+
+impl LocalRef {
+    /// `number` is the numbered taxon of the target in the page, e.g. `Lemma 2.3. `.
+    pub fn to_html(&self, number: Option<&str>) -> String {
+        let text = match (&self.text, number) {
+            (Some(text), _) => text.to_string(),
+            (None, Some(number)) => number.trim().trim_end_matches('.').to_string(),
+            (None, None) => self.title.to_string(),
+        };
+        html_flake::html_link(
+            &config::full_html_url(&self.slug),
+            &format!("{} [{}]", self.title, self.slug),
+            &text,
+            State::LocalLink.strify(),
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -225,14 +413,263 @@ impl Section {
         self.metadata.slug().unwrap().to_string()
     }
 
+    /// Return a copy of this section containing only the selected `part`,
+    /// along with the text of the selected heading, if any.
+    /// Return `None` if the part cannot be found.
+    pub fn select(&self, part: &EmbedPart) -> Option<(Section, Option<String>)> {
+        let (children, heading) = match part {
+            EmbedPart::Whole => (self.children.clone(), None),
+            EmbedPart::Header => (vec![], None),
+            EmbedPart::Heading(anchor) => select_heading(&self.children, anchor)?,
+            EmbedPart::Region(name) => (select_region(&self.children, name)?, None),
+        };
+        let mut section = self.clone();
+        section.children = children;
+        Some((section, heading))
+    }
+
+    /// Return a copy of this section containing only the first `n` paragraphs,
+    /// where an embedded section counts as one paragraph,
+    /// and whether any contents have been cut off.
+    pub fn preview(&self, n: usize) -> (Section, bool) {
+        let mut section = self.clone();
+        let mut count = 0;
+        for (index, child) in self.children.iter().enumerate() {
+            if count == n {
+                section.children.truncate(index);
+                return (section, true);
+            }
+            match child {
+                SectionContent::Plain(html) => {
+                    for (pos, _) in html.match_indices("</p>") {
+                        count += 1;
+                        if count == n {
+                            let end = pos + "</p>".len();
+                            let truncated =
+                                !html[end..].trim().is_empty() || index + 1 < self.children.len();
+                            section.children[index] =
+                                SectionContent::Plain(html[..end].to_string());
+                            section.children.truncate(index + 1);
+                            return (section, truncated);
+                        }
+                    }
+                }
+                SectionContent::Embed(_) => count += 1,
+                SectionContent::Local(_) => (),
+            }
+        }
+        (section, false)
+    }
+
     pub fn spanned(&self) -> String {
         self.children
             .iter()
             .map(|content| match content {
                 SectionContent::Plain(html) => html.to_string(),
+                SectionContent::Local(local) => local.to_html(None),
                 SectionContent::Embed(_) => unreachable!(),
             })
             .reduce(|s, t| s + &t)
             .unwrap_or_default()
     }
 }
+
+/// Convert heading text to the anchor used by `#heading:anchor`,
+/// e.g. `Main Lemma` to `main-lemma`.
+pub fn heading_anchor(text: &str) -> String {
+    let text = HTMLContent::Plain(text.to_string()).remove_all_tags();
+    let mut anchor = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            anchor.extend(c.to_lowercase());
+        } else if !anchor.ends_with('-') {
+            anchor.push('-');
+        }
+    }
+    anchor.trim_end_matches('-').to_string()
+}
+
+fn select_heading(
+    children: &SectionContents,
+    anchor: &str,
+) -> Option<(SectionContents, Option<String>)> {
+    static RE_HEADING: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"<h(?<level>[1-6])(?<attrs>\s[^>]*)?>(?<text>[\s\S]*?)</h[1-6]>"#).unwrap()
+    });
+    static RE_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\bid="(?<id>[^"]*)""#).unwrap());
+
+    let mut selected: SectionContents = vec![];
+    let mut heading: Option<(usize, String)> = None;
+
+    for child in children {
+        let html = match child {
+            SectionContent::Plain(html) => html,
+            SectionContent::Embed(_) | SectionContent::Local(_) => {
+                if heading.is_some() {
+                    selected.push(child.clone());
+                }
+                continue;
+            }
+        };
+
+        let mut cursor = 0;
+        for capture in RE_HEADING.captures_iter(html) {
+            let all = capture.get(0).unwrap();
+            let level: usize = capture["level"].parse().unwrap();
+            match &heading {
+                None => {
+                    let text = &capture["text"];
+                    let id = capture
+                        .name("attrs")
+                        .and_then(|attrs| RE_ID.captures(attrs.as_str()))
+                        .map(|c| c["id"].to_string());
+                    if id.as_deref() == Some(anchor) || heading_anchor(text) == anchor {
+                        heading = Some((level, text.to_string()));
+                        cursor = all.end();
+                    }
+                }
+                Some((current, text)) if level <= *current => {
+                    selected.push(SectionContent::Plain(html[cursor..all.start()].to_string()));
+                    return Some((selected, Some(text.to_string())));
+                }
+                _ => (),
+            }
+        }
+        if heading.is_some() {
+            selected.push(SectionContent::Plain(html[cursor..].to_string()));
+        }
+    }
+
+    heading.map(|(_, text)| (selected, Some(text)))
+}
+
+fn select_region(children: &SectionContents, name: &str) -> Option<SectionContents> {
+    static RE_REGION: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"<!--\s*(?<end>end)?region(:\s*(?<name>[^\s]*?))?\s*-->"#).unwrap()
+    });
+
+    let mut selected: SectionContents = vec![];
+    // Nesting depth of regions inside the selected one, `None` before it is found.
+    let mut depth: Option<usize> = None;
+
+    for child in children {
+        let html = match child {
+            SectionContent::Plain(html) => html,
+            SectionContent::Embed(_) | SectionContent::Local(_) => {
+                if depth.is_some() {
+                    selected.push(child.clone());
+                }
+                continue;
+            }
+        };
+
+        let mut cursor = 0;
+        for capture in RE_REGION.captures_iter(html) {
+            let all = capture.get(0).unwrap();
+            let is_end = capture.name("end").is_some();
+            match depth {
+                None => {
+                    if !is_end && capture.name("name").map(|s| s.as_str()) == Some(name) {
+                        depth = Some(0);
+                        cursor = all.end();
+                    }
+                }
+                Some(0) if is_end => {
+                    selected.push(SectionContent::Plain(html[cursor..all.start()].to_string()));
+                    return Some(selected);
+                }
+                Some(n) => depth = Some(if is_end { n - 1 } else { n + 1 }),
+            }
+        }
+        if depth.is_some() {
+            selected.push(SectionContent::Plain(html[cursor..].to_string()));
+        }
+    }
+
+    // An unterminated region extends to the end of the entry.
+    depth.map(|_| selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plains(htmls: &[&str]) -> SectionContents {
+        htmls
+            .iter()
+            .map(|html| SectionContent::Plain(html.to_string()))
+            .collect()
+    }
+
+    fn joined(contents: &SectionContents) -> String {
+        contents
+            .iter()
+            .map(|content| match content {
+                SectionContent::Plain(html) => html.as_str(),
+                _ => "[child]",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_url_by_part() {
+        let part = |url: &str| EmbedPart::split_url(url);
+        assert_eq!(part("/a.md"), ("/a.md".to_string(), EmbedPart::Whole));
+        assert_eq!(part("/a.md#"), ("/a.md".to_string(), EmbedPart::Whole));
+        assert_eq!(part("/a.md#header").1, EmbedPart::Header);
+        assert_eq!(
+            part("/a.md#heading:main-lemma").1,
+            EmbedPart::Heading("main-lemma".to_string())
+        );
+        assert_eq!(
+            part("/a.md#proof").1,
+            EmbedPart::Heading("proof".to_string())
+        );
+        assert_eq!(
+            part("/a.md#region:setup").1,
+            EmbedPart::Region("setup".to_string())
+        );
+    }
+
+    #[test]
+    fn select_heading_up_to_the_same_level() {
+        let children = plains(&[
+            "<p>Intro</p><h2>Main Lemma</h2><p>Statement</p>",
+            r#"<h3 id="proof">Proof</h3><p>Trivial</p><h2>Next</h2><p>Rest</p>"#,
+        ]);
+
+        let (selected, title) = select_heading(&children, "main-lemma").unwrap();
+        assert_eq!(title.as_deref(), Some("Main Lemma"));
+        assert_eq!(
+            joined(&selected),
+            r#"<p>Statement</p><h3 id="proof">Proof</h3><p>Trivial</p>"#
+        );
+
+        let (selected, _) = select_heading(&children, "proof").unwrap();
+        assert_eq!(joined(&selected), "<p>Trivial</p>");
+        assert!(select_heading(&children, "missing").is_none());
+    }
+
+    #[test]
+    fn select_nested_and_unterminated_regions() {
+        let children = plains(&[
+            "<p>Before</p><!-- region: outer --><p>A</p><!-- region: inner --><p>B</p>",
+            "<!-- endregion --><p>C</p><!-- endregion --><p>After</p><!--region:tail--><p>D</p>",
+        ]);
+
+        let outer = select_region(&children, "outer").unwrap();
+        assert_eq!(
+            joined(&outer),
+            "<p>A</p><!-- region: inner --><p>B</p><!-- endregion --><p>C</p>"
+        );
+        assert_eq!(
+            joined(&select_region(&children, "inner").unwrap()),
+            "<p>B</p>"
+        );
+        assert_eq!(
+            joined(&select_region(&children, "tail").unwrap()),
+            "<p>D</p>"
+        );
+        assert!(select_region(&children, "missing").is_none());
+    }
+}
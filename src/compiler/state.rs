@@ -4,13 +4,20 @@ use eyre::OptionExt;
 
 use crate::{
     config,
-    entry::{EntryMetaData, HTMLMetaData, MetaData, KEY_SLUG},
+    entry::{EntryMetaData, HTMLMetaData, MetaData, KEY_DATA_TAXON, KEY_LANG, KEY_SLUG, KEY_TAXON},
+    process::embed_markdown::display_taxon,
     slug,
 };
 
 use super::{
+    bibliography::BIB_DIR,
     callback::Callback,
-    section::{HTMLContent, LazyContent, Section, SectionContent, SectionContents, ShallowSection},
+    glossary::Glossary,
+    people::People,
+    section::{
+        EmbedPart, HTMLContent, LazyContent, LocalLink, LocalRef, Section, SectionContent,
+        SectionContents, ShallowSection,
+    },
     taxon::Taxon,
 };
 
@@ -18,19 +25,41 @@ use super::{
 pub struct CompileState {
     residued: BTreeSet<String>,
     compiled: HashMap<String, Section>,
+
+    /// Slugs of the sections being compiled, i.e. embedding the current one.
+    compiling: HashSet<String>,
     callback: Callback,
+    people: People,
+
+    /// Titles of the unpublished entries by their slugs.
+    unpublished: HashMap<String, String>,
 }
 
 type Shallows = HashMap<String, ShallowSection>;
 
-pub fn compile_all(mut shallows: Shallows) -> eyre::Result<CompileState> {
+/// `unpublished` are the titles of the entries removed from `shallows` by their slugs,
+/// see [`super::draft::take_unpublished`].
+pub fn compile_all(
+    mut shallows: Shallows,
+    unpublished: HashMap<String, String>,
+) -> eyre::Result<CompileState> {
     for shallow in shallows.values_mut() {
         shallow.metadata.compute_textual_attrs();
     }
 
+    let glossary = Glossary::new(&shallows);
+    for shallow in shallows.values_mut() {
+        glossary.autolink(shallow);
+    }
+
+    let mut people = People::new(&shallows);
+    for shallow in shallows.values_mut() {
+        people.resolve(shallow);
+    }
+
     let residued: BTreeSet<String> = shallows.keys().cloned().collect();
 
-    let mut state = CompileState::new(residued);
+    let mut state = CompileState::new(residued, people, unpublished);
     state
         .compile(&shallows, "index")
         .ok_or_eyre("missing `index` section, please provide `index.md` or `index.typst`")?;
@@ -46,11 +75,18 @@ pub fn compile_all(mut shallows: Shallows) -> eyre::Result<CompileState> {
 }
 
 impl CompileState {
-    fn new(residued: BTreeSet<String>) -> CompileState {
+    fn new(
+        residued: BTreeSet<String>,
+        people: People,
+        unpublished: HashMap<String, String>,
+    ) -> CompileState {
         CompileState {
             residued,
             compiled: HashMap::new(),
+            compiling: HashSet::new(),
             callback: Callback::new(),
+            people,
+            unpublished,
         }
     }
 
@@ -70,6 +106,7 @@ impl CompileState {
 
     fn compile_shallow(&mut self, shallows: &Shallows, shallow: &ShallowSection) -> &Section {
         let slug = shallow.slug();
+        self.compiling.insert(slug.to_string());
         let mut children: SectionContents = vec![];
         let mut references: HashSet<String> = HashSet::new();
 
@@ -87,6 +124,13 @@ impl CompileState {
                         }
                         LazyContent::Embed(embed_content) => {
                             let child_slug = slug::to_slug(&embed_content.url);
+                            if self.unpublished.contains_key(&child_slug) {
+                                eprintln!(
+                                    "Warning: [{}] skips embedding the unpublished [{}].",
+                                    slug, child_slug,
+                                );
+                                continue;
+                            }
                             let refered = match self.fetch_section(shallows, &child_slug) {
                                 Some(refered_section) => refered_section,
                                 None => {
@@ -98,52 +142,138 @@ impl CompileState {
                                 }
                             };
 
-                            if embed_content.option.details_open {
-                                references.extend(refered.references.clone());
-                            }
-                            callback.insert_parent(child_slug, slug.to_string());
+                            let Some((mut child_section, heading)) =
+                                refered.select(&embed_content.part)
+                            else {
+                                eprintln!(
+                                    "Error: [{}] attempting to embed a non-existent part {:?} of [{}].",
+                                    slug, embed_content.part, child_slug,
+                                );
+                                continue;
+                            };
 
-                            let mut child_section = refered.clone();
-                            child_section.option = embed_content.option.clone();
-                            if let Some(title) = &embed_content.title {
+                            if let Some(title) = embed_content.title.as_ref().or(heading.as_ref()) {
                                 child_section
                                     .metadata
                                     .update("title".to_string(), title.to_string())
                             };
-                            children.push(SectionContent::Embed(child_section));
-                        }
-                        LazyContent::Local(local_link) => {
-                            let link_slug = &local_link.slug;
-                            let article_title = get_metadata(shallows, link_slug)
-                                .map_or("", |s| s.page_title().map_or("", |s| s));
-
-                            if is_reference(shallows, link_slug) {
-                                references.insert(link_slug.to_string());
+                            if let Some(taxon) = &embed_content.option.taxon {
+                                let lang = child_section
+                                    .metadata
+                                    .get(KEY_LANG)
+                                    .unwrap_or(&config::project_config().lang)
+                                    .to_string();
+                                let (taxon, data_taxon) =
+                                    Taxon::resolve(&display_taxon(taxon), &lang);
+                                child_section
+                                    .metadata
+                                    .update(KEY_DATA_TAXON.to_string(), data_taxon);
+                                child_section.metadata.update(KEY_TAXON.to_string(), taxon);
                             }
+                            child_section.option = embed_content.option.clone();
+                            child_section.option.inherit_taxon(
+                                child_section.metadata.data_taxon().map(|s| s.as_str()),
+                            );
 
-                            /*
-                             * Making oneself the content of a backlink should not be expected behavior.
-                             */
-                            if *link_slug != slug
-                                && format!("{}:metadata", link_slug) != slug
-                                && is_enable_backlinks(shallows, link_slug)
+                            if child_section.option.details_open()
+                                && embed_content.part != EmbedPart::Header
                             {
-                                callback.insert_backlinks(
-                                    link_slug.to_string(),
-                                    vec![slug.to_string()],
-                                );
+                                references.extend(refered.references.clone());
                             }
+                            callback.insert_parent(child_slug, slug.to_string());
 
-                            let local_link = local_link.text.clone();
-                            let text = local_link.unwrap_or(article_title.to_string());
+                            children.push(SectionContent::Embed(child_section));
+                        }
+                        LazyContent::Query(query_content) => {
+                            let matched = query_content.query.evaluate(shallows, &slug);
+                            if !query_content.query.embed {
+                                let items: Vec<String> = matched
+                                    .iter()
+                                    .map(|child_slug| {
+                                        let metadata = get_metadata(shallows, child_slug).unwrap();
+                                        let title = metadata.page_title().map_or("", |s| s);
+                                        let taxon = metadata.taxon().map(|s| s.remove_all_tags());
+                                        crate::html_flake::html_query_item(
+                                            child_slug,
+                                            title,
+                                            &taxon.unwrap_or_default(),
+                                            "",
+                                        )
+                                    })
+                                    .collect();
+                                let html = crate::html_flake::html_query_list(&items);
+                                children.push(SectionContent::Plain(html));
+                                continue;
+                            }
 
-                            let html = crate::html_flake::html_link(
-                                &config::full_html_url(link_slug),
-                                &format!("{} [{}]", article_title, link_slug),
-                                &text,
-                                crate::recorder::State::LocalLink.strify(),
+                            for child_slug in matched {
+                                if self.compiling.contains(&child_slug) {
+                                    eprintln!(
+                                        "Warning: [{}] skips the query match [{}], which is embedding [{}].",
+                                        slug, child_slug, slug,
+                                    );
+                                    continue;
+                                }
+                                let Some(refered) = self.fetch_section(shallows, &child_slug)
+                                else {
+                                    continue;
+                                };
+                                let mut child_section = refered.clone();
+                                child_section.option = query_content.option.clone();
+                                child_section.option.inherit_taxon(
+                                    child_section.metadata.data_taxon().map(|s| s.as_str()),
+                                );
+                                if child_section.option.details_open() {
+                                    references.extend(refered.references.clone());
+                                }
+                                callback.insert_parent(child_slug, slug.to_string());
+                                children.push(SectionContent::Embed(child_section));
+                            }
+                        }
+                        LazyContent::Local(local_link) => {
+                            if let Some(title) = self.unpublished.get(&local_link.slug) {
+                                eprintln!(
+                                    "Warning: [{}] links to the unpublished [{}] as plain text.",
+                                    slug, local_link.slug,
+                                );
+                                let text = local_link.text.as_ref().unwrap_or(title);
+                                children.push(SectionContent::Plain(text.to_string()));
+                                continue;
+                            }
+                            let local = local_ref(
+                                shallows,
+                                &slug,
+                                local_link,
+                                &mut references,
+                                &mut callback,
                             );
-                            children.push(SectionContent::Plain(html.to_string()));
+                            children.push(local);
+                        }
+                        LazyContent::Citation(slugs) => {
+                            children.push(SectionContent::Plain("[".to_string()));
+                            for (index, link_slug) in slugs.iter().enumerate() {
+                                if index > 0 {
+                                    children.push(SectionContent::Plain("; ".to_string()));
+                                }
+                                // An unknown citation displays its key.
+                                let text = (!shallows.contains_key(link_slug)).then(|| {
+                                    let prefix = format!("{}/", BIB_DIR);
+                                    format!("?{}", link_slug.trim_start_matches(&prefix))
+                                });
+                                let local_link = LocalLink {
+                                    slug: link_slug.to_string(),
+                                    text,
+                                };
+                                let local = local_ref(
+                                    shallows,
+                                    &slug,
+                                    &local_link,
+                                    &mut references,
+                                    &mut callback,
+                                );
+                                children.push(local);
+                            }
+                            children.push(SectionContent::Plain("]".to_string()));
                         }
                     }
                 }
@@ -168,6 +298,7 @@ impl CompileState {
 
         // remove from `self.residued` after compiled.
         self.residued.remove(&slug);
+        self.compiling.remove(&slug);
 
         let section = Section::new(metadata, children, references);
         self.compiled.insert(slug.to_string(), section);
@@ -194,6 +325,48 @@ impl CompileState {
     pub fn callback(&self) -> &Callback {
         &self.callback
     }
+
+    pub fn people(&self) -> &People {
+        &self.people
+    }
+
+    pub fn is_unpublished(&self, slug: &str) -> bool {
+        self.unpublished.contains_key(slug)
+    }
+}
+
+/// Resolve the local link in the section `current`,
+/// recording the reference and the backlink to it.
+fn local_ref(
+    shallows: &Shallows,
+    current: &str,
+    local_link: &LocalLink,
+    references: &mut HashSet<String>,
+    callback: &mut Callback,
+) -> SectionContent {
+    let link_slug = &local_link.slug;
+    let article_title =
+        get_metadata(shallows, link_slug).map_or("", |s| s.page_title().map_or("", |s| s));
+
+    if is_reference(shallows, link_slug) {
+        references.insert(link_slug.to_string());
+    }
+
+    /*
+     * Making oneself the content of a backlink should not be expected behavior.
+     */
+    if *link_slug != current
+        && format!("{}:metadata", link_slug) != current
+        && is_enable_backlinks(shallows, link_slug)
+    {
+        callback.insert_backlinks(link_slug.to_string(), vec![current.to_string()]);
+    }
+
+    SectionContent::Local(LocalRef {
+        slug: link_slug.to_string(),
+        title: article_title.to_string(),
+        text: local_link.text.clone(),
+    })
 }
 
 fn get_metadata<'s>(shallows: &'s Shallows, slug: &str) -> Option<&'s HTMLMetaData> {
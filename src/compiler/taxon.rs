@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{config, process::embed_markdown::display_taxon};
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Taxon {
     pub numbering: Option<String>,
@@ -30,8 +32,34 @@ impl Taxon {
         }
     }
 
-    pub fn is_reference(s: &str) -> bool {
-        s.to_lowercase().starts_with("reference.") || s.starts_with("参考")
+    pub fn is_reference(data_taxon: &str) -> bool {
+        config::project_config()
+            .taxon(data_taxon)
+            .is_some_and(|(_, taxon)| taxon.reference.unwrap_or(false))
+    }
+
+    pub fn is_person(data_taxon: &str) -> bool {
+        config::project_config()
+            .taxon(data_taxon)
+            .is_some_and(|(_, taxon)| taxon.person.unwrap_or(false))
+    }
+
+    /// Resolve the displayed taxon to `(display, data_taxon)` by the taxon registry.
+    /// A registered taxon is displayed by its name in `lang`, and its data-taxon is
+    /// the capitalized registry key, e.g. both `Bibliographie. ` and `Reference. `
+    /// become `Reference`.
+    pub fn resolve(taxon_display: &str, lang: &str) -> (String, String) {
+        let data_taxon = Self::to_data_taxon(taxon_display);
+        match config::project_config().taxon(data_taxon) {
+            Some((key, taxon)) => {
+                let display = taxon
+                    .name
+                    .get(lang)
+                    .map_or(taxon_display.to_string(), display_taxon);
+                (display, capitalize(key))
+            }
+            None => (taxon_display.to_string(), data_taxon.to_string()),
+        }
     }
 
     pub fn to_data_taxon(taxon_display: &str) -> &str {
@@ -41,3 +69,10 @@ impl Taxon {
         &taxon_display[0..dot]
     }
 }
+
+fn capitalize(s: &str) -> String {
+    match s.split_at_checked(1) {
+        Some((first, rest)) => first.to_uppercase() + rest,
+        None => s.to_string(),
+    }
+}
@@ -1,23 +1,33 @@
 use eyre::{eyre, WrapErr};
 
 use super::html_parser::{HTMLParser, HTMLTagKind};
-use super::section::{EmbedContent, LocalLink, SectionOption};
+use super::query::Query;
+use super::section::{EmbedContent, EmbedPart, LocalLink, QueryContent, SectionOption};
 use super::section::{HTMLContent, HTMLContentBuilder, LazyContent};
 use super::ShallowSection;
+use crate::config;
 use crate::entry::HTMLMetaData;
 use crate::process::embed_markdown;
 use crate::slug::to_slug;
 use crate::typst_cli;
-use std::borrow::Cow;
+use crate::typst_deps::file_dependencies;
+use regex_lite::Regex;
 use std::collections::HashMap;
 use std::str;
+use std::sync::LazyLock;
 
-fn parse_bool(m: Option<&Cow<'_, str>>, def: bool) -> bool {
-    match m.map(|s| s.as_ref()) {
-        None | Some("auto") => def,
-        Some("false") | Some("0") | Some("none") => false,
-        _ => true,
-    }
+/// `#meta("title", "...")` of `kodama.typ` with a string literal.
+static RE_TITLE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\bmeta\(\s*"title"\s*,\s*"(?<title>(?:[^"\\]|\\.)*)"\s*\)"#).unwrap()
+});
+
+/// The title of the `.typst` entry for `sys.inputs.title`, which is known before it is
+/// compiled only if it is given by a string literal.
+fn source_title(relative_path: &str) -> Option<String> {
+    let src = std::fs::read_to_string(config::join_path(&config::root_dir(), relative_path));
+    let src = src.ok()?;
+    let title = RE_TITLE.captures(&src)?.name("title")?.as_str();
+    Some(title.replace("\\\"", "\"").replace("\\\\", "\\"))
 }
 
 fn parse_typst_html(
@@ -65,19 +75,36 @@ fn parse_typst_html(
                 metadata.insert(key.to_string(), val);
             }
             HTMLTagKind::Embed => {
-                let def = SectionOption::default();
-
-                let url = attr("url")?.to_string();
+                let (url, part) = EmbedPart::split_url(attr("url")?);
                 let title = value();
-                let numbering = parse_bool(span.attrs.get("numbering"), def.numbering);
-                let details_open = parse_bool(span.attrs.get("open"), def.details_open);
-                let catalog = parse_bool(span.attrs.get("catalog"), def.catalog);
+                let mut option = SectionOption::default();
+                for (key, value) in &span.attrs {
+                    if !matches!(*key, "url" | "value") {
+                        option
+                            .set(key, Some(value))
+                            .wrap_err_with(|| eyre!("invalid embed option in `{relative_path}`"))?;
+                    }
+                }
                 builder.push(LazyContent::Embed(EmbedContent {
                     url,
                     title,
-                    option: SectionOption::new(numbering, details_open, catalog),
+                    option,
+                    part,
                 }))
             }
+            HTMLTagKind::Query => {
+                let query = Query::parse(attr("query")?)
+                    .wrap_err_with(|| eyre!("invalid query in `{relative_path}`"))?;
+                let mut option = SectionOption::default();
+                for (key, value) in &span.attrs {
+                    if *key != "query" {
+                        option
+                            .set(key, Some(value))
+                            .wrap_err_with(|| eyre!("invalid embed option in `{relative_path}`"))?;
+                    }
+                }
+                builder.push(LazyContent::Query(QueryContent { query, option }))
+            }
             HTMLTagKind::Local { span: _ } => {
                 let slug = to_slug(attr("slug")?);
                 let text = value();
@@ -93,7 +120,10 @@ fn parse_typst_html(
 
 pub fn parse_typst(slug: &str, root_dir: &str) -> eyre::Result<ShallowSection> {
     let relative_path = format!("{}.typst", slug);
-    let html_str = typst_cli::file_to_html(&relative_path, root_dir)
+    config::record_dependencies(file_dependencies(&relative_path));
+    let title = source_title(&relative_path);
+    let inputs = typst_cli::page_inputs(slug, title.as_deref());
+    let html_str = typst_cli::file_to_html(&relative_path, root_dir, &inputs)
         .wrap_err_with(|| eyre!("failed to compile typst file `{relative_path}` to html"))?;
 
     let mut metadata: HashMap<String, HTMLContent> = HashMap::new();
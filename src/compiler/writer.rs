@@ -1,26 +1,70 @@
-use std::{collections::HashSet, ops::Not, path::Path};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Not,
+    path::Path,
+};
 
 use crate::{
     compiler::counter::Counter,
-    config::{self, verify_update_hash},
-    entry::MetaData,
+    config::{self, verify_update_hash, NumberingReset},
+    entry::{EntryMetaData, MetaData, KEY_SLUG, KEY_TAXON, KEY_TITLE},
     html,
     html_flake::{self, html_article_inner},
 };
 
 use super::{
     callback::CallbackValue,
-    section::{Section, SectionContent},
+    listing::Listing,
+    section::{LocalRef, Section, SectionContent},
     state::CompileState,
     taxon::Taxon,
 };
 
 pub struct Writer {}
 
+/// Numbered taxa of the sections in each page, see [`Numbering`].
+pub type PageNumbers = HashMap<String, HashMap<String, String>>;
+
+/// Numbered taxa of the sections in a page keyed by slug, e.g. `Theorem 1.2. `,
+/// recorded while the page is rendered. The local links are rendered at last,
+/// as they may display the numbers of the sections after them.
+#[derive(Default)]
+pub struct Numbering {
+    numbers: HashMap<String, String>,
+    links: Vec<LocalRef>,
+}
+
+impl Numbering {
+    fn link(&mut self, local: &LocalRef) -> String {
+        self.links.push(local.clone());
+        Numbering::placeholder(self.links.len() - 1)
+    }
+
+    fn placeholder(index: usize) -> String {
+        format!("<!-- local-ref:{} -->", index)
+    }
+
+    fn resolve(&self, html: &str) -> String {
+        let mut html = html.to_string();
+        for (index, local) in self.links.iter().enumerate() {
+            let number = self.numbers.get(&local.slug).map(String::as_str);
+            html = html.replace(&Numbering::placeholder(index), &local.to_html(number));
+        }
+        html
+    }
+}
+
 impl Writer {
-    pub fn write(section: &Section, state: &CompileState) {
-        let (html, page_title) = Writer::html_doc(section, state);
-        let html_url = format!("{}.html", section.slug());
+    /// Return the numbered taxa in the page.
+    pub fn write(section: &Section, state: &CompileState) -> HashMap<String, String> {
+        let (html, page_title, numbers) = Writer::html_doc(section, state);
+        Writer::write_html(&section.slug(), &html, &page_title);
+        numbers
+    }
+
+    fn write_html(slug: &str, html: &str, page_title: &str) {
+        let html_url = format!("{}.html", slug);
         let filepath = crate::config::output_path(&html_url);
 
         let relative_path = config::join_path(&config::output_dir(), &html_url);
@@ -35,7 +79,8 @@ impl Writer {
         }
     }
 
-    pub fn write_needed_slugs(all_slugs: &Vec<String>, state: &CompileState) {
+    pub fn write_needed_slugs(all_slugs: &[String], state: &CompileState) -> PageNumbers {
+        let mut page_numbers = PageNumbers::new();
         all_slugs
             .iter()
             .for_each(|slug| match state.compiled().get(slug) {
@@ -45,14 +90,55 @@ impl Writer {
                  * of the [`Section`].
                  */
                 None => eprintln!("Slug `{}` not in compiled entries.", slug),
-                Some(section) => Writer::write(section, &state),
+                Some(section) => {
+                    let numbers = Writer::write(section, state);
+                    page_numbers.insert(slug.to_string(), numbers);
+                }
             });
+        page_numbers
+    }
+
+    pub fn write_listings(listings: &[Listing], state: &CompileState) {
+        for listing in listings {
+            if state.compiled().contains_key(&listing.slug) {
+                eprintln!(
+                    "Generated page `{}` collides with an existing entry, skipped.",
+                    listing.slug
+                );
+                continue;
+            }
+            let html = Writer::listing_doc(listing, state);
+            Writer::write_html(&listing.slug, &html, &listing.title);
+        }
+    }
+
+    fn listing_doc(listing: &Listing, state: &CompileState) -> String {
+        let mut metadata = EntryMetaData(HashMap::new());
+        metadata.update(KEY_SLUG.to_string(), listing.slug.to_string());
+        metadata.update(KEY_TITLE.to_string(), listing.title.to_string());
+        metadata.update(KEY_TAXON.to_string(), listing.taxon.to_string());
+
+        let article_inner =
+            html_article_inner(&metadata, &listing.content, false, true, false, None, None);
+        crate::html_flake::html_doc(
+            &listing.title,
+            &Writer::nav(state, "index"),
+            &article_inner,
+            "",
+            "",
+        )
     }
 
-    pub fn html_doc(section: &Section, state: &CompileState) -> (String, String) {
-        let mut counter = Counter::init();
+    pub fn html_doc(
+        section: &Section,
+        state: &CompileState,
+    ) -> (String, String, HashMap<String, String>) {
+        let mut counter = Counter::starting_at(section.metadata.numbering_start());
 
-        let (article_inner, items) = Writer::section_to_html(section, &mut counter, true, false);
+        let mut numbering = Numbering::default();
+        let (article_inner, items) =
+            Writer::section_to_html(section, &mut counter, &mut numbering, true, false);
+        let article_inner = numbering.resolve(&article_inner);
         let catalog_html = items
             .is_empty()
             .not()
@@ -63,7 +149,7 @@ impl Writer {
         let html_header = Writer::header(state, &slug);
 
         let callback = state.callback().0.get(&slug);
-        let footer_html = Writer::footer(state, &section.references, callback);
+        let footer_html = Writer::footer(state, &slug, &section.references, callback);
         let page_title = section.metadata.page_title().map_or("", |s| s.as_str());
 
         let html = crate::html_flake::html_doc(
@@ -74,7 +160,7 @@ impl Writer {
             &catalog_html,
         );
 
-        (html, page_title.to_string())
+        (html, page_title.to_string(), numbering.numbers)
     }
 
     fn header(state: &CompileState, slug: &str) -> String {
@@ -82,55 +168,89 @@ impl Writer {
             .callback()
             .0
             .get(slug)
-            .and_then(|callback| {
-                let parent = &callback.parent;
-                state.compiled().get(parent).map(|section| {
-                    let href = config::full_html_url(parent);
-                    let title = section.metadata.title().map_or("", |s| s);
-                    let page_title = section.metadata.page_title().map_or("", |s| s);
-                    html_flake::html_header_nav(title, page_title, &href)
-                })
+            .map(|callback| Writer::nav(state, &callback.parent))
+            .unwrap_or_default()
+    }
+
+    fn nav(state: &CompileState, parent: &str) -> String {
+        state
+            .compiled()
+            .get(parent)
+            .map(|section| {
+                let href = config::full_html_url(parent);
+                let title = section.metadata.title().map_or("", |s| s);
+                let page_title = section.metadata.page_title().map_or("", |s| s);
+                html_flake::html_header_nav(title, page_title, &href)
             })
             .unwrap_or_default()
     }
 
     fn footer(
         state: &CompileState,
+        slug: &str,
         references: &HashSet<String>,
         callback: Option<&CallbackValue>,
     ) -> String {
-        let mut references: Vec<&String> = references.iter().collect();
-        references.sort();
-
-        let references_html = references
-            .iter()
-            .map(|slug| {
-                let slug = slug.to_string();
-                let section = state.compiled().get(&slug).unwrap();
-                Writer::footer_section_to_html(section)
-            })
+        let references_html = Writer::footer_sections(state, references)
+            .into_iter()
+            .map(Writer::footer_section_to_html)
             .reduce(|s, t| s + &t)
             .map(|s| html_flake::html_footer_section("References", &s))
             .unwrap_or_default();
 
+        /*
+         * Entries listed as authored or contributed are not repeated in backlinks.
+         */
+        let authored = state.people().authored(slug);
+        let contributed = state.people().contributed(slug);
+        let is_listed = |slug: &String| {
+            authored.is_some_and(|s| s.contains(slug))
+                || contributed.is_some_and(|s| s.contains(slug))
+        };
+        let people_html = [("Authored", authored), ("Contributions", contributed)]
+            .into_iter()
+            .filter_map(|(summary, slugs)| {
+                Writer::footer_sections(state, slugs?)
+                    .into_iter()
+                    .map(Writer::footer_section_to_html)
+                    .reduce(|s, t| s + &t)
+                    .map(|s| html_flake::html_footer_section(summary, &s))
+            })
+            .collect::<String>();
+
         let backlinks_html = callback
             .map(|s| {
-                let mut backlinks: Vec<&String> = s.backlinks.iter().collect();
-                backlinks.sort();
-                backlinks
+                let backlinks: HashSet<String> = s
+                    .backlinks
                     .iter()
-                    .map(|slug| {
-                        let slug = Writer::clip_metadata_badge(slug);
-                        let section = state.compiled().get(&slug).unwrap();
-                        Writer::footer_section_to_html(section)
-                    })
+                    .map(|slug| Writer::clip_metadata_badge(slug))
+                    .filter(|slug| !is_listed(slug))
+                    .collect();
+                Writer::footer_sections(state, &backlinks)
+                    .into_iter()
+                    .map(Writer::footer_section_to_html)
                     .reduce(|s, t| s + &t)
                     .map(|s| html_flake::html_footer_section("Backlinks", &s))
                     .unwrap_or_default()
             })
             .unwrap_or_default();
 
-        html!(footer => (references_html) (backlinks_html))
+        html!(footer => (references_html) (people_html) (backlinks_html))
+    }
+
+    /// The compiled sections of the slugs, ordered by `sort.footers` in `kodama.toml`.
+    fn footer_sections<I>(state: &CompileState, slugs: I) -> Vec<&Section>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut sections: Vec<&Section> = slugs
+            .into_iter()
+            .filter_map(|slug| state.compiled().get(slug.as_ref()))
+            .collect();
+        let sort = config::project_config().sort.footers;
+        sections.sort_by(|a, b| sort.compare(&a.metadata, &b.metadata));
+        sections
     }
 
     fn clip_metadata_badge(slug: &str) -> String {
@@ -153,7 +273,7 @@ impl Writer {
             slug,
             title,
             page_title,
-            section.option.details_open,
+            section.option.details_open(),
             taxon,
             child_html,
         )
@@ -163,6 +283,7 @@ impl Writer {
         match content {
             SectionContent::Plain(s) => s.to_string(),
             SectionContent::Embed(section) => Writer::footer_section_to_html(section),
+            SectionContent::Local(local) => local.to_html(None),
         }
     }
 
@@ -182,38 +303,86 @@ impl Writer {
                         .reduce(|s, t| s + &t)
                         .unwrap(),
                 };
-                html_article_inner(&section.metadata, &contents, false, false, None, None)
+                html_article_inner(
+                    &section.metadata,
+                    &contents,
+                    false,
+                    false,
+                    false,
+                    None,
+                    None,
+                )
             }
         }
     }
 
+    /// Counter of the children of a section numbered by `numbers`.
+    fn subcounter(counter: &Counter, numbers: Option<Vec<usize>>) -> Counter {
+        match (config::project_config().numbering.reset, numbers) {
+            (NumberingReset::Section, Some(numbers)) => Counter::left_shift_by(numbers),
+            _ => counter.clone(),
+        }
+    }
+
+    /// With [`NumberingReset::Page`], the numbering continues after the children.
+    fn continue_counter(counter: &mut Counter, subcounter: Counter) {
+        if config::project_config().numbering.reset == NumberingReset::Page {
+            *counter = subcounter;
+        }
+    }
+
+    /// Apply the `preview` option, and return whether any contents have been cut off.
+    fn previewed(section: &Section) -> (Cow<'_, Section>, bool) {
+        match section.option.preview {
+            Some(n) => {
+                let (section, truncated) = section.preview(n);
+                (Cow::Owned(section), truncated)
+            }
+            None => (Cow::Borrowed(section), false),
+        }
+    }
+
+    /// The numbered taxa are recorded in `numbering`,
+    /// which are displayed by the local links to these sections.
     pub fn section_to_html(
         section: &Section,
         counter: &mut Counter,
+        numbering: &mut Numbering,
         toplevel: bool,
         hide_metadata: bool,
     ) -> (String, String) {
-        let adhoc_taxon = Writer::taxon(section, counter);
-        let (contents, items) = match section.children.len() > 0 {
+        let (adhoc_taxon, section_numbers) = Writer::taxon(section, counter);
+        if section.option.numbering() {
+            numbering
+                .numbers
+                .entry(section.slug())
+                .or_insert(adhoc_taxon.clone());
+        }
+        let (section, truncated) = Writer::previewed(section);
+        let section = section.as_ref();
+        let (mut contents, items) = match section.children.len() > 0 {
             false => (String::new(), String::new()),
             true => {
-                let mut subcounter = match section.option.numbering {
-                    true => counter.left_shift(),
-                    false => counter.clone(),
-                };
+                let mut subcounter = Writer::subcounter(counter, section_numbers);
                 let content_to_html = |c: &SectionContent| {
                     let is_collection = section.metadata.is_collect();
-                    Writer::content_to_html(c, &mut subcounter, !is_collection)
+                    Writer::content_to_html(c, &mut subcounter, numbering, !is_collection)
                 };
-                section
+                let html = section
                     .children
                     .iter()
                     .map(content_to_html)
                     .reduce(|s, t| (s.0 + &t.0, s.1 + &t.1))
-                    .unwrap()
+                    .unwrap();
+                Writer::continue_counter(counter, subcounter);
+                html
             }
         };
 
+        if truncated {
+            contents.push_str(&html_flake::html_read_more(&section.slug()));
+        }
+
         let child_html = items
             .is_empty()
             .not()
@@ -229,14 +398,23 @@ impl Writer {
                 .unwrap_or(String::new()),
         };
 
-        let article_inner = html_article_inner(
-            &section.metadata,
-            &contents,
-            hide_metadata,
-            section.option.details_open,
-            None,
-            Some(adhoc_taxon.as_str()),
-        );
+        let article_inner = match section.option.header {
+            true => html_article_inner(
+                &section.metadata,
+                &contents,
+                hide_metadata || !section.option.metadata,
+                section.option.details_open(),
+                section.option.card,
+                None,
+                Some(adhoc_taxon.as_str()),
+            ),
+            false => html_flake::html_section_headless(
+                &contents,
+                section.option.card,
+                section.metadata.id(),
+                section.metadata.data_taxon(),
+            ),
+        };
 
         (article_inner, catalog_item)
     }
@@ -244,24 +422,28 @@ impl Writer {
     fn content_to_html(
         content: &SectionContent,
         counter: &mut Counter,
+        numbering: &mut Numbering,
         hide_metadata: bool,
     ) -> (String, String) {
         match content {
             SectionContent::Plain(s) => (s.to_string(), String::new()),
             SectionContent::Embed(section) => {
-                Writer::section_to_html(section, counter, false, hide_metadata)
+                Writer::section_to_html(section, counter, numbering, false, hide_metadata)
             }
+            SectionContent::Local(local) => (numbering.link(local), String::new()),
         }
     }
 
-    fn taxon(section: &Section, counter: &mut Counter) -> String {
-        if section.option.numbering {
-            counter.step_mut();
-            let numbering = Some(counter.display());
-            let text = section.metadata.taxon().map_or("", |s| s);
+    /// The displayed taxon, and the numbers if the section is numbered.
+    fn taxon(section: &Section, counter: &mut Counter) -> (String, Option<Vec<usize>>) {
+        let text = section.metadata.taxon().map_or("", |s| s);
+        if section.option.numbering() {
+            let data_taxon = section.metadata.data_taxon().map_or("", |s| s);
+            let numbers = counter.step_mut(&config::project_config().counter_name(data_taxon));
+            let numbering = Some(Counter::display(&numbers));
             let taxon = Taxon::new(numbering, text.to_string());
-            return taxon.display();
+            return (taxon.display(), Some(numbers));
         }
-        section.metadata.taxon().map_or("", |s| s).to_string()
+        (text.to_string(), None)
     }
 }
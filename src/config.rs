@@ -1,10 +1,15 @@
+mod project;
+
+pub use project::{MathRenderer, NumberingReset, NumberingStyle, ProjectConfig};
+
 use std::{
     fs::{self, create_dir_all},
     hash::Hash,
-    path::{Path, PathBuf},
-    sync::{LazyLock, Mutex},
+    path::{Component, Path, PathBuf},
+    sync::{LazyLock, Mutex, OnceLock},
 };
 
+use eyre::{eyre, WrapErr};
 use walkdir::WalkDir;
 
 #[derive(Clone, clap::ValueEnum)]
@@ -32,6 +37,9 @@ pub struct CompileConfig<S> {
 
     /// `false`: This is very useful for users who want to modify existing styles or configure other themes.
     pub disable_export_css: bool,
+
+    /// Also write the drafts, the scheduled and the private entries.
+    pub drafts: bool,
 }
 
 impl CompileConfig<&'static str> {
@@ -44,6 +52,7 @@ impl CompileConfig<&'static str> {
             short_slug: true,
             footer_mode: FooterMode::Link,
             disable_export_css: true,
+            drafts: false,
         }
     }
 }
@@ -58,26 +67,22 @@ impl CompileConfig<String> {
             short_slug: true,
             footer_mode: FooterMode::Link,
             disable_export_css: true,
+            drafts: false,
         }
     }
 
-    pub fn new<'a>(
-        root_dir: String,
-        output_dir: String,
-        base_url: String,
-        disable_pretty_urls: bool,
-        short_slug: bool,
-        footer_mode: FooterMode,
-        disable_export_css: bool,
-    ) -> CompileConfig<String> {
+    /// The project in `root_dir` written to `output_dir`, with the other options of
+    /// [`DEFAULT_CONFIG`], which are overridden by the struct update syntax.
+    pub fn new(root_dir: String, output_dir: String) -> CompileConfig<String> {
         CompileConfig {
             root_dir,
             output_dir,
-            base_url: normalize_base_url(&base_url),
-            page_suffix: to_page_suffix(disable_pretty_urls),
-            short_slug,
-            footer_mode,
-            disable_export_css,
+            base_url: DEFAULT_CONFIG.base_url.to_string(),
+            page_suffix: DEFAULT_CONFIG.page_suffix.to_string(),
+            short_slug: DEFAULT_CONFIG.short_slug,
+            footer_mode: DEFAULT_CONFIG.footer_mode.clone(),
+            disable_export_css: DEFAULT_CONFIG.disable_export_css,
+            drafts: DEFAULT_CONFIG.drafts,
         }
     }
 }
@@ -99,6 +104,26 @@ pub static CUSTOM_MATH_HTML: LazyLock<String> = LazyLock::new(|| {
         .unwrap_or(include_str!("include/import-math.html").to_string())
 });
 
+pub static PROJECT_CONFIG: OnceLock<ProjectConfig> = OnceLock::new();
+
+/// Load `kodama.toml` in the root directory, if any.
+pub fn load_project_config() -> eyre::Result<()> {
+    let path = join_path(&root_dir(), PROJECT_CONFIG_NAME);
+    let project_config = match fs::read_to_string(&path) {
+        Ok(content) => {
+            toml::from_str(&content).wrap_err_with(|| eyre!("failed to parse `{path}`"))?
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => ProjectConfig::default(),
+        Err(err) => return Err(err).wrap_err_with(|| eyre!("failed to read `{path}`")),
+    };
+    let _ = PROJECT_CONFIG.set(project_config.with_builtins());
+    Ok(())
+}
+
+pub fn project_config() -> &'static ProjectConfig {
+    PROJECT_CONFIG.get_or_init(|| ProjectConfig::default().with_builtins())
+}
+
 pub fn lock_config() -> std::sync::MutexGuard<'static, CompileConfig<std::string::String>> {
     CONFIG.lock().unwrap()
 }
@@ -109,10 +134,18 @@ pub struct Blink {
     pub target: String,
 }
 
+pub const PROJECT_CONFIG_NAME: &str = "kodama.toml";
 pub const CACHE_DIR_NAME: &str = ".cache";
 pub const BUFFER_FILE_NAME: &str = "buffer";
 pub const HASH_DIR_NAME: &str = "hash";
 pub const ENTRY_DIR_NAME: &str = "entry";
+pub const INLINE_DIR_NAME: &str = "inline";
+pub const PAGES_DIR_NAME: &str = "pages";
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+pub const FINGERPRINT_FILE_NAME: &str = "fingerprint";
+
+/// Version of the format of the cached entries, increased on incompatible changes.
+pub const CACHE_VERSION: u32 = 2;
 
 pub fn mutex_set<T>(source: &Mutex<T>, target: T) {
     let mut guard = source.lock().unwrap();
@@ -138,6 +171,10 @@ pub fn is_short_slug() -> bool {
     lock_config().short_slug
 }
 
+pub fn is_drafts() -> bool {
+    lock_config().drafts
+}
+
 pub fn root_dir() -> String {
     lock_config().root_dir.to_string()
 }
@@ -218,12 +255,67 @@ pub fn auto_create_dir_path(paths: Vec<&str>) -> String {
     filepath.to_str().unwrap().to_string()
 }
 
+/// Paths of the output and cache files requested by this build, in order,
+/// relative to the project root if possible.
+pub static PRODUCED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+fn record_produced(path: &str) {
+    PRODUCED.lock().unwrap().push(relative_to_root(path));
+}
+
+/// `path` under the project root relative to it, as recorded by the build.
+pub fn relative_to_root(path: &str) -> String {
+    let root_dir = root_dir();
+    let path = Path::new(path);
+    let relative: PathBuf = path
+        .strip_prefix(&root_dir)
+        .unwrap_or(path)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    relative.to_str().unwrap().replace("\\", "/")
+}
+
+/// Forget the files produced and depended on by a previous build in this process.
+pub fn clear_records() {
+    PRODUCED.lock().unwrap().clear();
+    DEPENDED.lock().unwrap().clear();
+}
+
+/// Number of the files produced so far, to take the files produced after it
+/// by [`produced_since`].
+pub fn produced_len() -> usize {
+    PRODUCED.lock().unwrap().len()
+}
+
+pub fn produced_since(start: usize) -> Vec<String> {
+    PRODUCED.lock().unwrap()[start..].to_vec()
+}
+
+/// Typst dependencies of the sources parsed by this build, in order, relative to the
+/// project root. See [`crate::typst_deps`].
+pub static DEPENDED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+pub fn record_dependencies<I: IntoIterator<Item = String>>(paths: I) {
+    DEPENDED.lock().unwrap().extend(paths);
+}
+
+pub fn depended_len() -> usize {
+    DEPENDED.lock().unwrap().len()
+}
+
+pub fn depended_since(start: usize) -> Vec<String> {
+    DEPENDED.lock().unwrap()[start..].to_vec()
+}
+
 pub fn buffer_path() -> String {
     join_path(&get_cache_dir(), BUFFER_FILE_NAME)
 }
 
 pub fn output_path(path: &str) -> String {
-    auto_create_dir_path(vec![&output_dir(), path])
+    let path = auto_create_dir_path(vec![&output_dir(), path]);
+    record_produced(&path);
+    path
 }
 
 pub fn hash_dir() -> String {
@@ -231,7 +323,75 @@ pub fn hash_dir() -> String {
 }
 
 pub fn hash_path(path: &str) -> PathBuf {
-    auto_create_dir_path(vec![&hash_dir(), path]).into()
+    let path = auto_create_dir_path(vec![&hash_dir(), path]);
+    record_produced(&path);
+    path.into()
+}
+
+pub fn inline_dir() -> String {
+    join_path(&get_cache_dir(), INLINE_DIR_NAME)
+}
+
+/// Path of the cached inline SVG with the key, shared across all pages.
+pub fn inline_path(key: u64) -> String {
+    let path = auto_create_dir_path(vec![&inline_dir(), &format!("{:016x}.svg", key)]);
+    record_produced(&path);
+    path
+}
+
+pub fn manifest_path() -> String {
+    join_path(&get_cache_dir(), MANIFEST_FILE_NAME)
+}
+
+/// Fingerprint of everything the cached entries depend on besides their sources:
+/// the cache format, the kodama version, the compile options, `kodama.toml` and
+/// the `import-*.html` files.
+fn cache_fingerprint() -> String {
+    let root_dir = root_dir();
+    let read = |name: &str| fs::read_to_string(join_path(&root_dir, name)).unwrap_or_default();
+    let (base_url, page_suffix, short_slug, drafts) = {
+        let config = lock_config();
+        (
+            config.base_url.to_string(),
+            config.page_suffix.to_string(),
+            config.short_slug,
+            config.drafts,
+        )
+    };
+    let parts = [
+        CACHE_VERSION.to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+        base_url,
+        page_suffix,
+        short_slug.to_string(),
+        drafts.to_string(),
+        read(PROJECT_CONFIG_NAME),
+        read("import-meta.html"),
+        read("import-fonts.html"),
+        read("import-math.html"),
+    ];
+    format!("{:016x}", stable_hash(parts.join("\0").as_bytes()))
+}
+
+/// Discard the cached hashes and entries if the [`cache_fingerprint`] has changed
+/// since the last build, and return whether any of them is discarded.
+pub fn verify_cache_fingerprint() -> eyre::Result<bool> {
+    let path = join_path(&get_cache_dir(), FINGERPRINT_FILE_NAME);
+    let fingerprint = cache_fingerprint();
+    if fs::read_to_string(&path).is_ok_and(|s| s.trim() == fingerprint) {
+        return Ok(false);
+    }
+
+    let mut discarded = false;
+    for dir in [hash_dir(), entry_dir()] {
+        if Path::new(&dir).exists() {
+            fs::remove_dir_all(&dir).wrap_err_with(|| eyre!("failed to discard `{dir}`"))?;
+            discarded = true;
+        }
+    }
+    create_dir_all(get_cache_dir()).wrap_err("failed to create the cache directory")?;
+    fs::write(&path, fingerprint).wrap_err_with(|| eyre!("failed to write `{path}`"))?;
+    Ok(discarded)
 }
 
 pub fn entry_dir() -> String {
@@ -239,14 +399,22 @@ pub fn entry_dir() -> String {
 }
 
 pub fn entry_path(path: &str) -> PathBuf {
-    auto_create_dir_path(vec![&entry_dir(), path]).into()
+    let path = auto_create_dir_path(vec![&entry_dir(), path]);
+    record_produced(&path);
+    path.into()
+}
+
+/// 64-bit FNV-1a hash, which is stable across Rust versions and platforms
+/// unlike [`std::hash::DefaultHasher`].
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 /// Return is file modified i.e. is hash updated.
 pub fn is_hash_updated<P: AsRef<Path>>(content: &str, hash_path: P) -> (bool, u64) {
-    let mut hasher = std::hash::DefaultHasher::new();
-    std::hash::Hash::hash(&content, &mut hasher);
-    let current_hash = std::hash::Hasher::finish(&hasher);
+    let current_hash = stable_hash(content.as_bytes());
 
     let history_hash = std::fs::read_to_string(&hash_path)
         .map(|s| s.parse::<u64>().expect("Invalid hash"))
@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::compiler::query::Sort;
+
+/// Project configuration in `kodama.toml` at the project root.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProjectConfig {
+    /// Language of the site, e.g. `en-US` or `zh-CN`, which selects the taxon names.
+    pub lang: String,
+
+    /// BibTeX files cited by `[@key]`, relative to the project root.
+    pub bibliography: Vec<String>,
+
+    /// Files and directories excluded from the project, in gitignore syntax as in `.kodamaignore`.
+    pub ignore: Vec<String>,
+
+    /// Globs of the slugs of the private entries, which are written only with `--drafts`.
+    pub private: Vec<String>,
+
+    /// Generated index pages.
+    pub index: IndexConfig,
+
+    /// Numbering of the embedded entries.
+    pub numbering: NumberingConfig,
+
+    /// Glossary terms declared by the `terms` metadata.
+    pub glossary: GlossaryConfig,
+
+    /// Display of the `date` and `updated` metadata.
+    pub date: DateConfig,
+
+    /// Order of the entries in the generated pages and the footers.
+    pub sort: SortConfig,
+
+    /// Names of the numbered figures and tables.
+    pub figures: FiguresConfig,
+
+    /// Renderer of the Markdown math, unless the `math` metadata of the entry overrides it.
+    pub math: MathRenderer,
+
+    /// The Typst toolchain.
+    pub typst: TypstConfig,
+
+    /// The taxon registry, keyed by the lowercase data-taxon, e.g. `[taxa.definition]`.
+    pub taxa: HashMap<String, TaxonConfig>,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        ProjectConfig {
+            lang: "en-US".to_string(),
+            bibliography: vec![],
+            ignore: vec![],
+            private: vec!["private/**".to_string()],
+            index: IndexConfig::default(),
+            numbering: NumberingConfig::default(),
+            glossary: GlossaryConfig::default(),
+            date: DateConfig::default(),
+            sort: SortConfig::default(),
+            figures: FiguresConfig::default(),
+            math: MathRenderer::default(),
+            typst: TypstConfig::default(),
+            taxa: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct IndexConfig {
+    /// Generate `taxon/{taxon}.html` listing all entries of each taxon.
+    pub taxa: bool,
+
+    /// Generate `recent.html` listing this number of the latest dated entries.
+    pub recent: usize,
+
+    /// Generate `timeline.html` listing all dated entries by year and month.
+    pub timeline: bool,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        IndexConfig {
+            taxa: true,
+            recent: 0,
+            timeline: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DateConfig {
+    /// Pattern of the dates in the headers, see [`crate::date::Date::format`].
+    pub format: String,
+}
+
+impl Default for DateConfig {
+    fn default() -> Self {
+        DateConfig {
+            format: "%Y-%m-%d".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SortConfig {
+    /// Entries of the tag and taxon pages.
+    pub listings: Sort,
+
+    /// Entries of the References and Backlinks footers.
+    pub footers: Sort,
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        SortConfig {
+            listings: Sort::default(),
+            footers: Sort::parse("slug").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TypstConfig {
+    /// The Typst executable, either a command on `PATH` or a path relative to the project root.
+    pub executable: String,
+
+    /// Directories of additional fonts, relative to the project root.
+    pub font_paths: Vec<String>,
+
+    /// Use only the fonts in `font-paths` and the fonts embedded in Typst.
+    pub ignore_system_fonts: bool,
+
+    /// Directory of the local packages, relative to the project root.
+    pub package_path: Option<String>,
+
+    /// Directory of the downloaded packages, relative to the project root,
+    /// e.g. vendored `@preview` packages for offline builds.
+    pub package_cache_path: Option<String>,
+
+    /// Additional arguments of `typst compile`.
+    pub args: Vec<String>,
+
+    /// The minimum version of the Typst executable, e.g. `0.13.0`, checked before compiling.
+    pub min_version: Option<String>,
+
+    /// `sys.inputs.theme` of the Typst files, `auto` as the colors follow the reader's
+    /// light or dark mode, or e.g. `light` for a site with a light background only.
+    pub theme: String,
+
+    /// Inline formulas and figures in Markdown.
+    pub inline: InlineTypstConfig,
+}
+
+impl Default for TypstConfig {
+    fn default() -> Self {
+        TypstConfig {
+            executable: "typst".to_string(),
+            font_paths: vec![],
+            ignore_system_fonts: false,
+            package_path: None,
+            package_cache_path: None,
+            args: vec![],
+            min_version: None,
+            theme: "auto".to_string(),
+            inline: InlineTypstConfig::default(),
+        }
+    }
+}
+
+/// Overridden in a page by the metadata `typst-{key}`, e.g. `typst-text-size: 12pt`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct InlineTypstConfig {
+    /// Typst file prepended to every inline formula and applied to every figure,
+    /// relative to the project root.
+    pub preamble: Option<String>,
+
+    /// `#set text(size: ..)` of the inline formulas.
+    pub text_size: String,
+
+    /// `#set text(font: ..)` of the inline formulas, the Typst default if `None`.
+    pub font: Option<String>,
+
+    /// `#set text(top-edge: ..)`, either a metric such as `cap-height` or a length.
+    pub top_edge: String,
+
+    /// `#set text(bottom-edge: ..)`, either a metric such as `baseline` or a length.
+    pub bottom_edge: String,
+
+    /// Margin of the inline formulas without `inline-x` or `inline-x-y`.
+    pub margin: String,
+}
+
+impl Default for InlineTypstConfig {
+    fn default() -> Self {
+        InlineTypstConfig {
+            preamble: None,
+            text_size: "15.427pt".to_string(),
+            font: None,
+            top_edge: "bounds".to_string(),
+            bottom_edge: "bounds".to_string(),
+            margin: "0em".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GlossaryConfig {
+    /// Link the first occurrence of each term in every entry, unless `autolink: false`.
+    pub autolink: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct NumberingConfig {
+    /// Style of each number, e.g. `1`, `i`, `I`, `a` or `A`.
+    pub style: NumberingStyle,
+
+    /// Display `1.2.` rather than `1.2`.
+    pub trailing_dot: bool,
+
+    /// Number all taxa by a shared counter, or each taxon by its own counter.
+    pub counters: CounterMode,
+
+    /// Where the numbering restarts.
+    pub reset: NumberingReset,
+}
+
+impl Default for NumberingConfig {
+    fn default() -> Self {
+        NumberingConfig {
+            style: NumberingStyle::default(),
+            trailing_dot: true,
+            counters: CounterMode::default(),
+            reset: NumberingReset::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NumberingStyle {
+    #[default]
+    Arabic,
+    Roman,
+    UpperRoman,
+    Alpha,
+    UpperAlpha,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CounterMode {
+    /// `Theorem 2.1, Lemma 2.2`.
+    #[default]
+    Shared,
+
+    /// `Theorem 2.1, Lemma 2.1`.
+    Taxon,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NumberingReset {
+    /// Nested numbers, e.g. `2.1`, restarting in each numbered section.
+    #[default]
+    Section,
+
+    /// Flat numbers continuing across the transclusions, restarting only per page.
+    Page,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FiguresConfig {
+    /// Name of the numbered figures, either a single name or one per language,
+    /// e.g. `figure = { en = "Fig.", de = "Abb." }`.
+    pub figure: Localized,
+
+    /// Name of the numbered tables.
+    pub table: Localized,
+}
+
+impl FiguresConfig {
+    /// Fill the languages unset by the project with the builtin names.
+    fn inherit_builtin(&mut self) {
+        self.figure.inherit(localized(&[
+            ("en", "Figure"),
+            ("zh", "图"),
+            ("de", "Abbildung"),
+        ]));
+        self.table.inherit(localized(&[
+            ("en", "Table"),
+            ("zh", "表"),
+            ("de", "Tabelle"),
+        ]));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MathRenderer {
+    /// `$x^2$` in LaTeX syntax, rendered by KaTeX in the browser.
+    #[default]
+    Katex,
+
+    /// `$x^2$` in Typst syntax, compiled to SVG as the inline Typst.
+    Typst,
+}
+
+impl MathRenderer {
+    pub fn parse(value: &str) -> eyre::Result<MathRenderer> {
+        match value.trim() {
+            "katex" => Ok(MathRenderer::Katex),
+            "typst" => Ok(MathRenderer::Typst),
+            value => eyre::bail!("unknown math renderer `{value}`, expected `katex` or `typst`"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TaxonConfig {
+    /// Display name, either a single name or one per language,
+    /// e.g. `name = { en = "Theorem", zh = "定理", de = "Satz" }`.
+    pub name: Localized,
+
+    /// Plural name used as the title of the index page, e.g. `Theorems`.
+    pub plural: Localized,
+
+    /// Other names recognized as this taxon, case-insensitive.
+    pub aliases: Vec<String>,
+
+    /// Links to entries of this taxon are listed in the References footer.
+    pub reference: Option<bool>,
+
+    /// Entries of this taxon are persons, named by the `author` and `contributors`
+    /// of other entries.
+    pub person: Option<bool>,
+
+    /// Embedded entries of this taxon are numbered unless specified otherwise.
+    pub numbering: Option<bool>,
+
+    /// Embedded entries of this taxon are collapsed unless specified otherwise.
+    pub collapsed: Option<bool>,
+
+    /// Name of the counter numbering this taxon, e.g. `counter = "theorem"` for lemmas
+    /// to share the counter of theorems.
+    pub counter: Option<String>,
+
+    /// Additional CSS class of the sections of this taxon.
+    pub class: Option<String>,
+
+    /// Overrides `index.taxa` for this taxon.
+    pub index: Option<bool>,
+}
+
+/// A text given once for all languages, or per language.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(from = "LocalizedRepr")]
+pub struct Localized(HashMap<String, String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LocalizedRepr {
+    Text(String),
+    ByLang(HashMap<String, String>),
+}
+
+impl From<LocalizedRepr> for Localized {
+    fn from(repr: LocalizedRepr) -> Self {
+        match repr {
+            LocalizedRepr::Text(text) => Localized(HashMap::from([(String::new(), text)])),
+            LocalizedRepr::ByLang(map) => Localized(map),
+        }
+    }
+}
+
+impl Localized {
+    /// The text of `lang`, falling back to its primary subtag (`zh` of `zh-CN`),
+    /// the text for all languages and then English.
+    pub fn get(&self, lang: &str) -> Option<&str> {
+        let primary = lang.split(['-', '_']).next().unwrap_or(lang);
+        [lang, primary, "", "en"]
+            .iter()
+            .find_map(|lang| self.0.get(*lang))
+            .map(String::as_str)
+    }
+
+    fn texts(&self) -> impl Iterator<Item = &String> {
+        self.0.values()
+    }
+
+    fn inherit(&mut self, builtin: Localized) {
+        for (lang, text) in builtin.0 {
+            self.0.entry(lang).or_insert(text);
+        }
+    }
+}
+
+impl TaxonConfig {
+    fn names(&self) -> impl Iterator<Item = &String> {
+        self.name
+            .texts()
+            .chain(self.plural.texts())
+            .chain(self.aliases.iter())
+    }
+
+    /// Fill the fields unset by the project with the builtin ones.
+    fn inherit(&mut self, builtin: TaxonConfig) {
+        self.name.inherit(builtin.name);
+        self.plural.inherit(builtin.plural);
+        self.aliases.extend(builtin.aliases);
+        self.reference = self.reference.or(builtin.reference);
+        self.person = self.person.or(builtin.person);
+        self.numbering = self.numbering.or(builtin.numbering);
+        self.collapsed = self.collapsed.or(builtin.collapsed);
+        self.counter = self.counter.take().or(builtin.counter);
+        self.class = self.class.take().or(builtin.class);
+        self.index = self.index.or(builtin.index);
+    }
+}
+
+fn localized(texts: &[(&str, &str)]) -> Localized {
+    Localized(
+        texts
+            .iter()
+            .map(|(lang, text)| (lang.to_string(), text.to_string()))
+            .collect(),
+    )
+}
+
+/// Taxa known without configuration.
+fn builtin_taxa() -> Vec<(&'static str, TaxonConfig)> {
+    vec![
+        (
+            "reference",
+            TaxonConfig {
+                name: localized(&[("en", "Reference"), ("zh", "参考文献"), ("de", "Referenz")]),
+                plural: localized(&[
+                    ("en", "References"),
+                    ("zh", "参考文献"),
+                    ("de", "Referenzen"),
+                ]),
+                aliases: vec!["参考".to_string()],
+                reference: Some(true),
+                ..Default::default()
+            },
+        ),
+        (
+            "person",
+            TaxonConfig {
+                name: localized(&[("en", "Person"), ("zh", "人物"), ("de", "Person")]),
+                plural: localized(&[("en", "People"), ("zh", "人物"), ("de", "Personen")]),
+                person: Some(true),
+                ..Default::default()
+            },
+        ),
+    ]
+}
+
+impl ProjectConfig {
+    /// Merge the builtin taxa into the registry, and the builtin names of the figures.
+    pub fn with_builtins(mut self) -> Self {
+        self.figures.inherit_builtin();
+        self.taxa = std::mem::take(&mut self.taxa)
+            .into_iter()
+            .map(|(key, taxon)| (key.to_lowercase(), taxon))
+            .collect();
+        for (key, builtin) in builtin_taxa() {
+            self.taxa
+                .entry(key.to_string())
+                .or_default()
+                .inherit(builtin);
+        }
+        self
+    }
+
+    /// Look up the registry by the key or any name of a taxon, case-insensitive.
+    pub fn taxon(&self, data_taxon: &str) -> Option<(&str, &TaxonConfig)> {
+        let data_taxon = data_taxon.trim().to_lowercase();
+        if let Some((key, taxon)) = self.taxa.get_key_value(&data_taxon) {
+            return Some((key, taxon));
+        }
+        self.taxa
+            .iter()
+            .find(|(_, taxon)| taxon.names().any(|s| s.to_lowercase() == data_taxon))
+            .map(|(key, taxon)| (key.as_str(), taxon))
+    }
+
+    /// Name of the counter numbering the taxon, see [`CounterMode`].
+    pub fn counter_name(&self, data_taxon: &str) -> String {
+        let taxon = self.taxon(data_taxon);
+        if let Some(counter) = taxon.and_then(|(_, taxon)| taxon.counter.as_ref()) {
+            return counter.to_lowercase();
+        }
+        match self.numbering.counters {
+            CounterMode::Shared => String::new(),
+            CounterMode::Taxon => {
+                taxon.map_or(data_taxon.to_lowercase(), |(key, _)| key.to_string())
+            }
+        }
+    }
+
+    pub fn is_taxon_indexed(&self, data_taxon: &str) -> bool {
+        self.taxon(data_taxon)
+            .and_then(|(_, taxon)| taxon.index)
+            .unwrap_or(self.index.taxa)
+    }
+}
@@ -0,0 +1,240 @@
+use std::sync::LazyLock;
+
+use eyre::{bail, eyre};
+use regex_lite::Regex;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// A date `YYYY-MM-DD`, optionally followed by the time `THH:MM[:SS[.fff]]` and the
+/// time zone `Z` or `+HH:MM`, as in ISO 8601. The reduced dates `YYYY` and `YYYY-MM`
+/// are accepted as well, e.g. the years of the bibliography.
+///
+/// Dates are compared as written, i.e. regardless of the time zone.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+
+    /// `(hour, minute, second)`
+    pub time: Option<(u8, u8, u8)>,
+    pub zone: Option<String>,
+}
+
+impl Date {
+    pub fn parse(s: &str) -> eyre::Result<Date> {
+        static RE_DATE: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(concat!(
+                r"^(?<year>\d{4})(?:-(?<month>\d{2})(?:-(?<day>\d{2})",
+                r"(?:[T ](?<hour>\d{2}):(?<minute>\d{2})(?::(?<second>\d{2})(?:\.\d+)?)?",
+                r"(?<zone>Z|[+-]\d{2}:?\d{2})?)?)?)?$"
+            ))
+            .unwrap()
+        });
+
+        let s = s.trim();
+        let captures = RE_DATE.captures(s).ok_or_else(|| {
+            eyre!("expected a date `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS`, found `{s}`")
+        })?;
+        let number = |name: &str| {
+            captures
+                .name(name)
+                .map(|m| m.as_str().parse::<u8>().unwrap())
+        };
+
+        let year = captures["year"].parse().unwrap();
+        let month = number("month");
+        let day = number("day");
+        if month.is_some_and(|m| !(1..=12).contains(&m)) {
+            bail!("invalid month in date `{s}`");
+        }
+        if let (Some(month), Some(day)) = (month, day) {
+            if day == 0 || day > days_in_month(year, month) {
+                bail!("invalid day in date `{s}`");
+            }
+        }
+
+        let time = match (number("hour"), number("minute")) {
+            (Some(hour), Some(minute)) => Some((hour, minute, number("second").unwrap_or(0))),
+            _ => None,
+        };
+        if time.is_some_and(|(h, m, s)| h > 23 || m > 59 || s > 59) {
+            bail!("invalid time in date `{s}`");
+        }
+
+        Ok(Date {
+            year,
+            month,
+            day,
+            time,
+            zone: captures.name("zone").map(|m| m.as_str().to_string()),
+        })
+    }
+
+    /// The current date in UTC.
+    pub fn today() -> Date {
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        // Civil date from the days since 1970-01-01, by Howard Hinnant's algorithm.
+        let days = (seconds / 86400) as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+        Date {
+            year: year as u16,
+            month: Some(month as u8),
+            day: Some(day as u8),
+            time: None,
+            zone: None,
+        }
+    }
+
+    /// The machine-readable form in the `datetime` attribute of `<time>`.
+    pub fn to_iso(&self) -> String {
+        let mut iso = format!("{:04}", self.year);
+        if let Some(month) = self.month {
+            iso.push_str(&format!("-{:02}", month));
+        }
+        if let Some(day) = self.day {
+            iso.push_str(&format!("-{:02}", day));
+        }
+        if let Some((hour, minute, second)) = self.time {
+            iso.push_str(&format!("T{:02}:{:02}:{:02}", hour, minute, second));
+        }
+        if let Some(zone) = &self.zone {
+            iso.push_str(zone);
+        }
+        iso
+    }
+
+    pub fn month_name(&self) -> Option<&'static str> {
+        self.month.map(|month| MONTHS[month as usize - 1])
+    }
+
+    /// Format by the `pattern` of `%Y`, `%m`, `%d`, `%e` (unpadded day), `%B` (month name),
+    /// `%b` (abbreviated month name), `%H`, `%M`, `%S` and `%%`. A reduced date is
+    /// displayed as `1984`, or `March 1984` if the pattern has the month name.
+    pub fn format(&self, pattern: &str) -> String {
+        let (Some(month), Some(day)) = (self.month, self.day) else {
+            return match (
+                self.month_name(),
+                pattern.contains("%B") || pattern.contains("%b"),
+            ) {
+                (Some(name), true) => format!("{} {}", name, self.year),
+                (Some(_), false) => self.to_iso(),
+                (None, _) => self.year.to_string(),
+            };
+        };
+        let (hour, minute, second) = self.time.unwrap_or_default();
+        let name = MONTHS[month as usize - 1];
+
+        let mut formatted = String::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                formatted.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => formatted.push_str(&format!("{:04}", self.year)),
+                Some('m') => formatted.push_str(&format!("{:02}", month)),
+                Some('d') => formatted.push_str(&format!("{:02}", day)),
+                Some('e') => formatted.push_str(&day.to_string()),
+                Some('B') => formatted.push_str(name),
+                Some('b') => formatted.push_str(&name[..3]),
+                Some('H') => formatted.push_str(&format!("{:02}", hour)),
+                Some('M') => formatted.push_str(&format!("{:02}", minute)),
+                Some('S') => formatted.push_str(&format!("{:02}", second)),
+                Some('%') => formatted.push('%'),
+                Some(c) => {
+                    formatted.push('%');
+                    formatted.push(c);
+                }
+                None => formatted.push('%'),
+            }
+        }
+        formatted
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dates_and_times() {
+        let date = Date::parse("2024-02-29T08:30+08:00").unwrap();
+        assert_eq!((date.year, date.month, date.day), (2024, Some(2), Some(29)));
+        assert_eq!(date.time, Some((8, 30, 0)));
+        assert_eq!(date.zone.as_deref(), Some("+08:00"));
+        assert_eq!(date.to_iso(), "2024-02-29T08:30:00+08:00");
+
+        let reduced = Date::parse(" 1984-03 ").unwrap();
+        assert_eq!(
+            (reduced.year, reduced.month, reduced.day),
+            (1984, Some(3), None)
+        );
+        assert_eq!(reduced.format("%B %Y"), "March 1984");
+        assert_eq!(Date::parse("1984").unwrap().format("%Y-%m-%d"), "1984");
+    }
+
+    #[test]
+    fn reject_invalid_dates() {
+        for s in [
+            "2024-02-30",
+            "2023-02-29",
+            "1900-02-29",
+            "2024-04-31",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-01-00",
+            "2024-01-01T24:00",
+            "2024-1-1",
+        ] {
+            assert!(Date::parse(s).is_err(), "`{s}` should be rejected");
+        }
+        assert!(Date::parse("2000-02-29").is_ok());
+    }
+
+    #[test]
+    fn format_by_pattern() {
+        let date = Date::parse("2024-03-05T07:08:09").unwrap();
+        assert_eq!(date.format("%e %b %Y, %H:%M:%S"), "5 Mar 2024, 07:08:09");
+        assert_eq!(date.format("%d/%m 100%% %q"), "05/03 100% %q");
+    }
+}
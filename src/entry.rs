@@ -1,6 +1,8 @@
 use crate::{
     compiler::{section::HTMLContent, taxon::Taxon},
-    config, html,
+    config,
+    date::Date,
+    html,
     html_flake::html_entry_header,
 };
 use serde::{Deserialize, Serialize};
@@ -34,7 +36,68 @@ pub const KEY_COLLECT: &'static str = "collect";
 /// Controls whether the current page process as reference.
 pub const KEY_ASREF: &'static str = "asref";
 
-const PRESET_METADATA: [&'static str; 9] = [
+/// `tags: [a, b]`:
+/// Tags of the current page, each of which has a generated page listing its entries.
+pub const KEY_TAGS: &'static str = "tags";
+
+/// `lang: de`:
+/// Language of the current page, overriding `lang` in `kodama.toml` to select the taxon name.
+pub const KEY_LANG: &'static str = "lang";
+
+/// `numbering-start: 3`:
+/// The first number of the numbered entries in the current page.
+pub const KEY_NUMBERING_START: &'static str = "numbering-start";
+
+/// `figure-numbering: true` or `[figure, table, equation]`:
+/// Number the figures, tables or equations in the current entry.
+pub const KEY_FIGURE_NUMBERING: &'static str = "figure-numbering";
+
+/// `terms: [compact, compactness]`:
+/// Glossary terms defined by the current entry, which also include its title.
+pub const KEY_TERMS: &'static str = "terms";
+
+/// `aliases: [compact set]`:
+/// Further terms of the current entry, e.g. abbreviations or inflections.
+pub const KEY_ALIASES: &'static str = "aliases";
+
+/// `autolink: bool`:
+/// Controls whether the glossary terms in the current entry are linked,
+/// overriding `glossary.autolink` in `kodama.toml`.
+pub const KEY_AUTOLINK: &'static str = "autolink";
+
+/// `author: [alice, bob]`:
+/// Authors of the current entry, linked to the person entries they name.
+pub const KEY_AUTHOR: &'static str = "author";
+
+/// `contributors: [carol]`:
+/// Contributors of the current entry, linked to the person entries they name.
+pub const KEY_CONTRIBUTORS: &'static str = "contributors";
+
+/// `date: 2024-03-01` or `2024-03-01T09:30:00+08:00`:
+/// Creation date of the current entry.
+pub const KEY_DATE: &'static str = "date";
+
+/// `updated: 2024-05-20`:
+/// Date of the last significant update of the current entry.
+pub const KEY_UPDATED: &'static str = "updated";
+
+/// `draft: bool`:
+/// Controls whether the current entry is a draft, which is written only with `--drafts`.
+pub const KEY_DRAFT: &'static str = "draft";
+
+/// `publish-after: 2024-06-01`:
+/// The current entry is written only from this date on, or with `--drafts`.
+pub const KEY_PUBLISH_AFTER: &'static str = "publish-after";
+
+/// `math: typst`:
+/// Renderer of the Markdown math in the current entry, overriding `math` in `kodama.toml`.
+pub const KEY_MATH: &'static str = "math";
+
+/// `typst-text-size: 12pt`, `typst-preamble: /algebra.typ` etc.:
+/// Settings of the inline Typst in the current page, overriding `[typst.inline]` in `kodama.toml`.
+pub const KEY_TYPST_PREFIX: &'static str = "typst-";
+
+const PRESET_METADATA: [&'static str; 23] = [
     KEY_TITLE,
     KEY_SLUG,
     KEY_TAXON,
@@ -44,6 +107,20 @@ const PRESET_METADATA: [&'static str; 9] = [
     KEY_BACKLINKS,
     KEY_COLLECT,
     KEY_ASREF,
+    KEY_TAGS,
+    KEY_LANG,
+    KEY_NUMBERING_START,
+    KEY_FIGURE_NUMBERING,
+    KEY_TERMS,
+    KEY_ALIASES,
+    KEY_AUTOLINK,
+    KEY_AUTHOR,
+    KEY_CONTRIBUTORS,
+    KEY_DATE,
+    KEY_UPDATED,
+    KEY_DRAFT,
+    KEY_PUBLISH_AFTER,
+    KEY_MATH,
 ];
 
 pub trait MetaData<V>
@@ -55,7 +132,7 @@ where
     fn keys<'a>(&'a self) -> Keys<'a, String, V>;
 
     fn is_custom_metadata(s: &str) -> bool {
-        !PRESET_METADATA.contains(&s)
+        !PRESET_METADATA.contains(&s) && !s.starts_with(KEY_TYPST_PREFIX)
     }
 
     /// Return all custom metadata keys without [`PRESET_METADATA`].
@@ -105,6 +182,12 @@ where
         return self.get_str(KEY_SLUG);
     }
 
+    fn tags(&self) -> Vec<String> {
+        self.get_str(KEY_TAGS)
+            .map(|s| parse_list(s))
+            .unwrap_or_default()
+    }
+
     fn is_enable_backlinks(&self) -> bool {
         return self.get_bool(&KEY_BACKLINKS).unwrap_or(true);
     }
@@ -116,6 +199,34 @@ where
     fn is_asref(&self) -> bool {
         return self.get_bool(&KEY_ASREF).unwrap_or(false);
     }
+
+    fn date(&self) -> Option<Date> {
+        self.get_str(KEY_DATE).and_then(|s| Date::parse(s).ok())
+    }
+
+    fn updated(&self) -> Option<Date> {
+        self.get_str(KEY_UPDATED).and_then(|s| Date::parse(s).ok())
+    }
+
+    fn numbering_start(&self) -> usize {
+        self.get_str(KEY_NUMBERING_START)
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(1)
+    }
+}
+
+/// Parse a list in metadata, i.e. `[a, b]`, `a, b` or `("a", "b")` from Typst.
+pub fn parse_list(s: &str) -> Vec<String> {
+    let s = s.trim();
+    let s = match (s.chars().next(), s.chars().last()) {
+        (Some('['), Some(']')) | (Some('('), Some(')')) => &s[1..s.len() - 1],
+        _ => s,
+    };
+    s.split(',')
+        .map(|item| item.trim().trim_matches('"').trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
 }
 
 impl MetaData<HTMLContent> for HTMLMetaData {
@@ -148,6 +259,16 @@ impl MetaData<String> for EntryMetaData {
 
 impl HTMLMetaData {
     pub fn compute_textual_attrs(&mut self) {
+        for key in [KEY_DATE, KEY_UPDATED] {
+            let Some(value) = self.get(key) else {
+                continue;
+            };
+            if let Err(err) = Date::parse(&value.remove_all_tags()) {
+                let slug = self.slug().map_or("", |s| s);
+                eprintln!("Error: [{}] {} in `{}`.", slug, err, key);
+            }
+        }
+
         if self.page_title().is_none() {
             if let Some(title) = self.title() {
                 self.0.insert(
@@ -159,10 +280,17 @@ impl HTMLMetaData {
 
         if self.data_taxon().is_none() {
             if let Some(taxon) = self.taxon() {
-                self.0.insert(
-                    KEY_DATA_TAXON.to_string(),
-                    HTMLContent::Plain(Taxon::to_data_taxon(&taxon.remove_all_tags()).to_string()),
-                );
+                let lang = self
+                    .get(KEY_LANG)
+                    .map(|s| s.remove_all_tags())
+                    .unwrap_or_else(|| config::project_config().lang.clone());
+                let (display, data_taxon) = Taxon::resolve(&taxon.remove_all_tags(), lang.trim());
+                if display != taxon.remove_all_tags() {
+                    self.0
+                        .insert(KEY_TAXON.to_string(), HTMLContent::Plain(display));
+                }
+                self.0
+                    .insert(KEY_DATA_TAXON.to_string(), HTMLContent::Plain(data_taxon));
             }
         }
     }
@@ -185,7 +313,25 @@ impl EntryMetaData {
             (html!(span class = {span_class.join(" ")} => {taxon}))
             {title} {" "}
             (html!(a class = "slug", href = {slug_url} => "["{&slug_text}"]"))))
-          (html!(html_entry_header(self.etc()))))
+          (html!(html_entry_header(self.tags(), self.byline(), self.etc()))))
+    }
+
+    /// The dates, authors and contributors shown in the header.
+    fn byline(&self) -> Vec<String> {
+        let mut byline = vec![];
+        if let Some(date) = self.get(KEY_DATE) {
+            byline.push(html!(span class = "date" => (display_date(date))));
+        }
+        if let Some(updated) = self.get(KEY_UPDATED) {
+            byline.push(html!(span class = "updated" => "Updated " (display_date(updated))));
+        }
+        if let Some(author) = self.get(KEY_AUTHOR) {
+            byline.push(html!(span class = "author" => (author)));
+        }
+        if let Some(contributors) = self.get(KEY_CONTRIBUTORS) {
+            byline.push(html!(span class = "contributors" => "with " (contributors)));
+        }
+        byline
     }
 
     /// hidden suffix `/index` in slug text.
@@ -205,3 +351,14 @@ impl EntryMetaData {
         let _ = self.0.insert(key, value);
     }
 }
+
+/// A valid date formatted by `date.format` in `kodama.toml`, otherwise as written.
+pub fn display_date(value: &str) -> String {
+    match Date::parse(value) {
+        Ok(date) => {
+            let text = date.format(&config::project_config().date.format);
+            html!(time datetime = {date.to_iso()} => (text))
+        }
+        Err(_) => value.to_string(),
+    }
+}
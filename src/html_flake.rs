@@ -4,6 +4,7 @@ use crate::{
     config,
     entry::{EntryMetaData, MetaData},
     html,
+    process::figure::FigureNumber,
 };
 
 pub fn html_article_inner(
@@ -11,6 +12,7 @@ pub fn html_article_inner(
     contents: &String,
     hide_metadata: bool,
     open: bool,
+    card: bool,
     adhoc_title: Option<&str>,
     adhoc_taxon: Option<&str>,
 ) -> String {
@@ -22,6 +24,7 @@ pub fn html_article_inner(
         contents,
         hide_metadata,
         open,
+        card,
         article_id,
         metadata.data_taxon(),
     )
@@ -39,6 +42,7 @@ pub fn html_section(
     content: &String,
     hide_metadata: bool,
     open: bool,
+    card: bool,
     id: String,
     data_taxon: Option<&String>,
 ) -> String {
@@ -46,7 +50,12 @@ pub fn html_section(
     if hide_metadata {
         class_name.push("hide-metadata");
     }
+    if card {
+        class_name.push("card");
+    }
     let data_taxon = data_taxon.map_or("", |s| s);
+    let taxon_class = taxon_class(data_taxon);
+    class_name.extend(taxon_class.as_deref());
     let open = open.then(|| "open").unwrap_or("");
     let inner_html = format!("{}{}", (html!(summary => {summary})), content);
     let html_details = format!(
@@ -58,8 +67,45 @@ pub fn html_section(
     html!(section class = {class_name.join(" ")}, data_taxon = {data_taxon} => {html_details})
 }
 
-pub fn html_entry_header(mut etc: Vec<String>) -> String {
+/// Section without the header, i.e. the taxon, title and metadata.
+pub fn html_section_headless(
+    content: &String,
+    card: bool,
+    id: String,
+    data_taxon: Option<&String>,
+) -> String {
+    let mut class_name: Vec<&str> = vec!["block", "hide-header"];
+    if card {
+        class_name.push("card");
+    }
+    let data_taxon = data_taxon.map_or("", |s| s);
+    let taxon_class = taxon_class(data_taxon);
+    class_name.extend(taxon_class.as_deref());
+    html!(section class = {class_name.join(" ")}, id = {id}, data_taxon = {data_taxon} => {content})
+}
+
+/// The CSS class of the taxon in the taxon registry.
+fn taxon_class(data_taxon: &str) -> Option<String> {
+    config::project_config()
+        .taxon(data_taxon)
+        .and_then(|(_, taxon)| taxon.class.clone())
+}
+
+pub fn html_read_more(slug: &str) -> String {
+    let slug_url = config::full_html_url(slug);
+    html!(p class = "read-more" => (html!(a href = {slug_url} => "Read more »")))
+}
+
+pub fn html_entry_header(
+    tags: Vec<String>,
+    mut byline: Vec<String>,
+    mut etc: Vec<String>,
+) -> String {
     let mut meta_items: Vec<String> = vec![];
+    meta_items.append(&mut byline);
+    if !tags.is_empty() {
+        meta_items.push(html_tags(&tags));
+    }
     meta_items.append(&mut etc);
 
     let items = meta_items
@@ -71,6 +117,19 @@ pub fn html_entry_header(mut etc: Vec<String>) -> String {
     html!(div class="metadata" => (html!(ul => {items})))
 }
 
+pub fn html_tags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| match crate::compiler::listing::tag_slug(tag) {
+            Some(slug) => {
+                let href = config::full_html_url(&slug);
+                html!(a class = "tag", href = {href} => "#" {tag})
+            }
+            None => html!(span class = "tag" => "#" {tag}),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
 pub fn catalog_item(
     slug: &str,
     title: &str,
@@ -96,32 +155,88 @@ pub fn catalog_item(
       (child_html))
 }
 
+pub fn html_query_item(slug: &str, page_title: &str, taxon: &str, context: &str) -> String {
+    let slug_url = config::full_html_url(slug);
+    let title_text = format!("{} [{}]", page_title, slug);
+    let context = match context.is_empty() {
+        true => String::new(),
+        false => html!(span class = "context" => (" · ") (context)),
+    };
+    html!(li =>
+      (html!(span class = "taxon" => {taxon}))
+      (html_link(&slug_url, &title_text, page_title, "local"))
+      (context))
+}
+
+pub fn html_query_list(items: &[String]) -> String {
+    html!(ul class = "query" => (items.concat()))
+}
+
 pub fn html_image(image_src: &str) -> String {
     format!(r#"<img src = "{image_src}" />"#)
 }
 
-pub fn html_figure(image_src: &str, center: bool, caption: String) -> String {
+pub fn html_figure(
+    image_src: &str,
+    center: bool,
+    caption: String,
+    number: Option<&FigureNumber>,
+) -> String {
     if !center {
         return html_image(image_src);
     }
-    let mut caption = caption;
-    if !caption.is_empty() {
-        caption = html!(figcaption => (caption))
-    }
-    html!(figure => (html_image(image_src)) (caption))
+    html_numbered_figure(&html_image(image_src), caption, number)
 }
 
-pub fn html_figure_code(image_src: &str, caption: String, code: String) -> String {
-    let mut caption = caption;
-    if !caption.is_empty() {
-        caption = html!(figcaption => (caption))
-    }
-    let figure = html!(figure => (html_image(image_src)) (caption));
+pub fn html_figure_code(
+    image_src: &str,
+    caption: String,
+    code: String,
+    number: Option<&FigureNumber>,
+) -> String {
+    let figure = html_numbered_figure(&html_image(image_src), caption, number);
     let pre = html!(pre => (code));
     let details = html!(details => (html!(summary => (figure))) (pre));
     details
 }
 
+pub fn html_numbered_figure(
+    content: &str,
+    caption: String,
+    number: Option<&FigureNumber>,
+) -> String {
+    html!(figure => (content) (html_figcaption(caption, number)))
+}
+
+/// Caption prefixed by the number if any, e.g. `Figure 3: caption`, with the label as its id.
+pub fn html_figcaption(caption: String, number: Option<&FigureNumber>) -> String {
+    let Some(number) = number else {
+        return match caption.is_empty() {
+            true => caption,
+            false => html!(figcaption => (caption)),
+        };
+    };
+    let caption = match (number.text.is_empty(), caption.trim().is_empty()) {
+        (true, _) => caption.trim().to_string(),
+        (false, true) => html!(span class = "figure-number" => (number.text)),
+        (false, false) => format!(
+            "{} {}",
+            html!(span class = "figure-number" => (number.text) ":"),
+            caption.trim()
+        ),
+    };
+    match &number.label {
+        Some(label) => html!(figcaption id = {label} => (caption)),
+        None => html!(figcaption => (caption)),
+    }
+}
+
+/// Display math followed by its number, e.g. `(3)`.
+pub fn html_numbered_equation(math: &str, number: &FigureNumber) -> String {
+    html!(span class = "equation" =>
+      (math) (html!(span class = "equation-number" => (number.text))))
+}
+
 pub fn html_link(href: &str, title: &str, text: &str, class_name: &str) -> String {
     html!(span class = format!("link {}", class_name) => 
       (html!(a href = {href}, title = {title} => {text})))
@@ -153,7 +268,8 @@ pub fn html_doc(
       "\n\n"
       (toc_html));
 
-    let html = html!(html lang = "en-US" => 
+    let lang = &config::project_config().lang;
+    let html = html!(html lang = {lang} => 
       (html!(head => r#"
 <meta http-equiv="Content-Type" content="text/html; charset=utf-8">
 <meta name="viewport" content="width=device-width">"#
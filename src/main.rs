@@ -1,5 +1,6 @@
 mod compiler;
 mod config;
+mod date;
 mod entry;
 mod html_flake;
 mod html_macro;
@@ -7,6 +8,9 @@ mod process;
 mod recorder;
 mod slug;
 mod typst_cli;
+mod typst_deps;
+#[cfg(feature = "embed-typst")]
+mod typst_lib;
 
 use config::{output_path, CompileConfig, FooterMode};
 
@@ -61,6 +65,10 @@ struct CompileCommand {
     /// Disable exporting the `*.css` file to the output directory.
     #[arg(long)]
     disable_export_css: bool,
+
+    /// Also write the drafts, the scheduled and the private entries, e.g. for local preview.
+    #[arg(long)]
+    drafts: bool,
 }
 
 #[derive(clap::Args)]
@@ -99,17 +107,20 @@ fn main() -> eyre::Result<()> {
 
             config::mutex_set(
                 &config::CONFIG,
-                CompileConfig::new(
-                    root.to_string(),
-                    output.to_string(),
-                    compile_command.base.to_string(),
-                    compile_command.disable_pretty_urls,
-                    compile_command.short_slug,
-                    compile_command.footer_mode.clone(),
-                    compile_command.disable_export_css,
-                ),
+                CompileConfig {
+                    base_url: config::normalize_base_url(&compile_command.base),
+                    page_suffix: config::to_page_suffix(compile_command.disable_pretty_urls),
+                    short_slug: compile_command.short_slug,
+                    footer_mode: compile_command.footer_mode.clone(),
+                    disable_export_css: compile_command.disable_export_css,
+                    drafts: compile_command.drafts,
+                    ..CompileConfig::new(root.to_string(), output.to_string())
+                },
             );
 
+            config::load_project_config()?;
+            typst_cli::check_version()?;
+
             if !compile_command.disable_export_css {
                 export_css_files().wrap_err("failed to export CSS")?;
             }
@@ -123,11 +134,6 @@ fn main() -> eyre::Result<()> {
                 CompileConfig::new(
                     clean_command.root.to_string(),
                     clean_command.output.to_string(),
-                    config::DEFAULT_CONFIG.base_url.into(),
-                    false,
-                    config::DEFAULT_CONFIG.short_slug,
-                    FooterMode::Link,
-                    true,
                 ),
             );
 
@@ -3,9 +3,17 @@ use std::collections::HashMap;
 
 use crate::{
     compiler::{
+        bibliography::bib_slug,
         parser::parse_spanned_markdown,
-        section::{EmbedContent, HTMLContent, LazyContent, LocalLink, SectionOption},
+        query::Query,
+        section::{
+            EmbedContent, EmbedPart, HTMLContent, LazyContent, LocalLink, QueryContent,
+            SectionOption,
+        },
     },
+    config::MathRenderer,
+    entry::{KEY_AUTOLINK, KEY_FIGURE_NUMBERING, KEY_LANG, KEY_MATH, KEY_TYPST_PREFIX},
+    html,
     html_flake::html_link,
     recorder::{ParseRecorder, State},
     slug::to_slug,
@@ -28,6 +36,14 @@ impl Processer for Embed {
                 if action == State::Embed.strify() {
                     recorder.enter(State::Embed);
                     recorder.push(url); // [0]
+                } else if action == State::Query.strify() {
+                    recorder.enter(State::Query);
+                    recorder.push(url); // [0]
+                } else if action == State::NoLink.strify() {
+                    recorder.enter(State::NoLink);
+                } else if is_citation(&url) {
+                    recorder.enter(State::Citation);
+                    recorder.push(url); // [0]
                 } else if is_external_link(&url) {
                     recorder.enter(State::ExternalLink);
                     recorder.push(url);
@@ -50,19 +66,43 @@ impl Processer for Embed {
     fn end(&mut self, tag: &TagEnd, recorder: &mut ParseRecorder) -> Option<LazyContent> {
         if *tag == TagEnd::Link && recorder.state == State::Embed {
             let entry_url = recorder.data.get(0).map_or("", |s| s);
-            let entry_url = crate::config::relativize(entry_url);
+            let (entry_url, part) = EmbedPart::split_url(entry_url);
+            let entry_url = crate::config::relativize(&entry_url);
 
-            let embed_text = recorder.data.get(1);
-            let (section_option, inline_title) = parse_embed_text(embed_text);
+            let embed_text = (recorder.data.len() > 1).then(|| recorder.data[1..].join(""));
+            let (section_option, inline_title) = parse_embed_text(embed_text.as_deref())
+                .unwrap_or_else(|err| {
+                    eprintln!("{:?} at {}", err, recorder.current);
+                    (SectionOption::default(), None)
+                });
 
             recorder.exit();
             return Some(LazyContent::Embed(EmbedContent {
                 url: entry_url,
                 title: inline_title,
                 option: section_option,
+                part,
             }));
         }
 
+        if *tag == TagEnd::Link && recorder.state == State::Query {
+            let query_url = recorder.data.get(0).map_or("", |s| s);
+            let query_text = (recorder.data.len() > 1).then(|| recorder.data[1..].join(""));
+            let parsed = Query::parse(query_url).and_then(|query| {
+                let (option, _) = parse_embed_text(query_text.as_deref())?;
+                Ok(QueryContent { query, option })
+            });
+
+            recorder.exit();
+            return match parsed {
+                Ok(query_content) => Some(LazyContent::Query(query_content)),
+                Err(err) => {
+                    eprintln!("{:?} at {}", err, recorder.current);
+                    Some(LazyContent::Plain(String::new()))
+                }
+            };
+        }
+
         if *tag == TagEnd::Link && recorder.state == State::LocalLink {
             let url = recorder
                 .data
@@ -80,6 +120,27 @@ impl Processer for Embed {
             }));
         }
 
+        if *tag == TagEnd::Link && recorder.state == State::Citation {
+            let slugs = recorder
+                .data
+                .first()
+                .map_or("", |s| s)
+                .split([';', ','])
+                .map(|key| key.trim().trim_start_matches('@'))
+                .filter(|key| !key.is_empty())
+                .map(bib_slug)
+                .collect();
+            recorder.exit();
+            return Some(LazyContent::Citation(slugs));
+        }
+
+        if *tag == TagEnd::Link && recorder.state == State::NoLink {
+            let text = recorder.data.join("");
+            recorder.exit();
+            let html = html!(span class = {State::NoLink.strify()} => (text));
+            return Some(LazyContent::Plain(html));
+        }
+
         if *tag == TagEnd::Link && recorder.state == State::ExternalLink {
             let url = recorder
                 .data
@@ -137,7 +198,12 @@ impl Processer for Embed {
 }
 
 fn allow_inline(state: &State) -> bool {
-    *state == State::Embed || *state == State::LocalLink || *state == State::ExternalLink
+    *state == State::Embed
+        || *state == State::Query
+        || *state == State::Citation
+        || *state == State::NoLink
+        || *state == State::LocalLink
+        || *state == State::ExternalLink
 }
 
 /// It is known that the behavior differs between the two architectures
@@ -149,6 +215,7 @@ pub fn parse_metadata(
     metadata: &mut HashMap<String, HTMLContent>,
     recorder: &mut ParseRecorder,
 ) -> eyre::Result<()> {
+    let mut entries = vec![];
     let lines: Vec<&str> = s.split("\n").collect();
     for s in lines {
         if s.trim().len() != 0 {
@@ -157,49 +224,88 @@ pub fn parse_metadata(
                 .ok_or_else(|| eyre!("expected metadata format `name: value`, found `{s}`"))?;
             let key = s[0..pos].trim();
             let val = s[pos + 1..].trim();
+            entries.push((key, val));
 
-            let mut val = parse_spanned_markdown(val, &format!("{}:metadata", recorder.current))
-                .wrap_err("failed to parse metadata value")?;
-            if key == "taxon" {
-                if let HTMLContent::Plain(v) = val {
-                    val = HTMLContent::Plain(display_taxon(&v));
-                }
+            if key == KEY_FIGURE_NUMBERING {
+                recorder.figure_counter.enable(val)?;
+            }
+            if key == KEY_LANG {
+                recorder.figure_counter.set_lang(val);
+            }
+            if key == KEY_MATH {
+                recorder.math = MathRenderer::parse(val)?;
+            }
+            if key == KEY_AUTOLINK {
+                recorder.autolink = val == "true";
+            }
+            if key == "title" {
+                recorder.title = Some(val.to_string());
+            }
+            if let Some(name) = key.strip_prefix(KEY_TYPST_PREFIX) {
+                recorder
+                    .typst_overrides
+                    .insert(name.to_string(), val.to_string());
+                recorder.inline_settings = None;
+            }
+        }
+    }
+
+    // The values are parsed with the settings of the whole metadata block, e.g. `math`.
+    for (key, val) in entries {
+        let mut val =
+            parse_spanned_markdown(val, recorder).wrap_err("failed to parse metadata value")?;
+        if key == "taxon" {
+            if let HTMLContent::Plain(v) = val {
+                val = HTMLContent::Plain(display_taxon(&v));
             }
-            metadata.insert(key.to_string(), val);
         }
+        metadata.insert(key.to_string(), val);
     }
     Ok(())
 }
 
-pub fn parse_embed_text(embed_text: Option<&String>) -> (SectionOption, Option<String>) {
-    match embed_text {
-        None => (SectionOption::default(), None),
-        Some(embed_text) => {
-            let mut numbering = false;
-            let mut details_open = true;
-            let mut catalog = true;
-
-            let mut index = 0;
-            let chars = embed_text.chars();
-            for curr in chars {
-                match curr {
-                    '+' => numbering = true,
-                    '-' => details_open = false,
-                    '.' => catalog = false,
-                    _ => break,
-                }
-                index += 1;
-            }
+/// Parse the embed text `[+-.]{option, key=value, ...} title`.
+/// Both the prefix flags and the option list are optional.
+pub fn parse_embed_text(embed_text: Option<&str>) -> eyre::Result<(SectionOption, Option<String>)> {
+    let mut option = SectionOption::default();
+    let Some(embed_text) = embed_text else {
+        return Ok((option, None));
+    };
 
-            let option = SectionOption::new(numbering, details_open, catalog);
-            let inline_title = &embed_text[index..].trim();
-            let inline_title = match !inline_title.is_empty() {
-                true => Some(inline_title.to_string()),
-                false => None,
-            };
-            (option, inline_title)
+    let mut index = 0;
+    let chars = embed_text.chars();
+    for curr in chars {
+        match curr {
+            '+' => option.numbering = Some(true),
+            '-' => option.details_open = Some(false),
+            '.' => option.catalog = false,
+            _ => break,
         }
+        index += 1;
     }
+
+    let mut inline_title = embed_text[index..].trim();
+    if let Some(options) = inline_title.strip_prefix('{') {
+        let end = options
+            .find('}')
+            .ok_or_else(|| eyre!("unclosed embed options `{inline_title}`"))?;
+        for item in options[..end].split(',').map(str::trim) {
+            if item.is_empty() {
+                continue;
+            }
+            match item.split_once('=') {
+                Some((key, value)) => option.set(key.trim(), Some(value.trim()))?,
+                None => option.set(item, None)?,
+            }
+        }
+        inline_title = options[end + 1..].trim();
+    }
+
+    let inline_title = match !inline_title.is_empty() {
+        true => Some(inline_title.to_string()),
+        false => None,
+    };
+    Ok((option, inline_title))
 }
 
 pub fn display_taxon(s: &str) -> String {
@@ -217,6 +323,16 @@ fn is_external_link(url: &str) -> bool {
     url.starts_with("http://") || url.starts_with("https://") || url.starts_with("www.")
 }
 
+/// `@key; @key`, see [`crate::compiler::parser::parse_content`].
+fn is_citation(url: &str) -> bool {
+    url.trim_start().starts_with('@')
+}
+
+/// Links within the entry, e.g. `[](#fig:plot)` or `[](#results)`, are not local links,
+/// see [`super::figure::resolve_figures`].
 fn is_local_link(url: &str) -> bool {
-    !super::typst_image::is_inline_typst(url) && !is_external_link(url) && !url.contains(":")
+    !super::typst_image::is_inline_typst(url)
+        && !is_external_link(url)
+        && !url.contains(":")
+        && !url.starts_with('#')
 }
@@ -1,7 +1,13 @@
+use std::{collections::HashMap, sync::LazyLock};
+
 use pulldown_cmark::{Tag, TagEnd};
+use regex_lite::{Captures, Regex};
 
 use crate::{
     compiler::section::{HTMLContent, LazyContent},
+    config,
+    entry::parse_list,
+    html_flake::{html_figcaption, html_numbered_figure},
     recorder::{ParseRecorder, State},
 };
 
@@ -21,18 +27,36 @@ impl Processer for Figure {
                 recorder.enter(State::Figure);
                 recorder.push(dest_url.to_string()); // [0]
             }
+            Tag::Table(_) if recorder.figure_counter.is_enabled(FigureKind::Table) => {
+                recorder.push(r#"<figure class="table"><table>"#.to_string());
+            }
             _ => (),
         }
     }
 
-    fn end(&mut self, _tag: &TagEnd, recorder: &mut ParseRecorder) -> Option<LazyContent> {
+    fn end(&mut self, tag: &TagEnd, recorder: &mut ParseRecorder) -> Option<LazyContent> {
         if recorder.state == State::Figure {
             let url = recorder.data.get(0).map_or("", |s| s);
             let alt = recorder.data.get(1).map_or("", |s| s);
+            let (alt, label) = split_label(alt);
             let html = format!(r#"<img src="{}" title="{}" alt="{}">"#, url, alt, alt);
+            let number = recorder.figure_counter.step_figure(label, &alt);
+            let html = match number {
+                Some(number) => html_numbered_figure(&html, alt, Some(&number)),
+                None => html,
+            };
             recorder.exit();
             return Some(LazyContent::Plain(html));
         }
+        if *tag == TagEnd::Table && recorder.figure_counter.is_enabled(FigureKind::Table) {
+            // The caption is filled in by [`resolve_figures`] from the paragraph `Table: ...`.
+            let number = recorder.figure_counter.step(FigureKind::Table, None)?;
+            let html = format!(
+                r#"</table><figcaption>{}</figcaption></figure>"#,
+                number.text
+            );
+            return Some(LazyContent::Plain(html));
+        }
         None
     }
 
@@ -48,3 +72,215 @@ impl Processer for Figure {
         Ok(())
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FigureKind {
+    Figure,
+    Table,
+    Equation,
+}
+
+impl FigureKind {
+    const ALL: [FigureKind; 3] = [FigureKind::Figure, FigureKind::Table, FigureKind::Equation];
+
+    fn name(&self) -> &'static str {
+        match self {
+            FigureKind::Figure => "figure",
+            FigureKind::Table => "table",
+            FigureKind::Equation => "equation",
+        }
+    }
+
+    /// `Figure 3`, `Table 3` or `(3)`, named by `[figures]` in `kodama.toml` in `lang`.
+    fn display(&self, n: usize, lang: &str) -> String {
+        let figures = &config::project_config().figures;
+        let name = match self {
+            FigureKind::Figure => figures.figure.get(lang),
+            FigureKind::Table => figures.table.get(lang),
+            FigureKind::Equation => return format!("({})", n),
+        };
+        format!("{} {}", name.unwrap_or_default(), n)
+    }
+}
+
+/// The displayed number of a figure, table or equation, and its label if any.
+/// The `text` is empty for a labeled figure which is not numbered.
+#[derive(Debug, Clone)]
+pub struct FigureNumber {
+    pub label: Option<String>,
+    pub text: String,
+}
+
+/// Numbering of the figures, tables and equations in an entry,
+/// enabled by the metadata `figure-numbering`.
+#[derive(Debug, Default)]
+pub struct FigureCounter {
+    enabled: Vec<FigureKind>,
+    counts: HashMap<FigureKind, usize>,
+
+    /// Label => displayed number, e.g. `fig:foo` => `Figure 3`.
+    labels: HashMap<String, String>,
+
+    /// The `lang` metadata of the entry, if any.
+    lang: Option<String>,
+}
+
+impl FigureCounter {
+    /// Enable the kinds by the metadata value, i.e. `true` for all kinds,
+    /// or a list of kinds such as `[figure, equation]`.
+    pub fn enable(&mut self, value: &str) -> eyre::Result<()> {
+        self.enabled = match value.trim() {
+            "true" => FigureKind::ALL.to_vec(),
+            "false" => vec![],
+            value => parse_list(value)
+                .iter()
+                .map(|name| {
+                    FigureKind::ALL
+                        .into_iter()
+                        .find(|kind| kind.name() == name.to_lowercase().trim_end_matches('s'))
+                        .ok_or_else(|| eyre::eyre!("unknown kind `{name}` in `figure-numbering`"))
+                })
+                .collect::<eyre::Result<_>>()?,
+        };
+        Ok(())
+    }
+
+    pub fn set_lang(&mut self, lang: &str) {
+        self.lang = Some(lang.to_string());
+    }
+
+    pub fn is_enabled(&self, kind: FigureKind) -> bool {
+        self.enabled.contains(&kind)
+    }
+
+    /// Step the counter of `kind` if it is enabled, and register the `label`.
+    pub fn step(&mut self, kind: FigureKind, label: Option<String>) -> Option<FigureNumber> {
+        if !self.is_enabled(kind) {
+            return None;
+        }
+        let n = self.counts.entry(kind).or_insert(0);
+        *n += 1;
+        let lang = self.lang.as_deref();
+        let text = kind.display(*n, lang.unwrap_or(&config::project_config().lang));
+        if let Some(label) = &label {
+            self.register(label, &text);
+        }
+        Some(FigureNumber { label, text })
+    }
+
+    /// Step the figure counter like [`Self::step`]. If the figures are not numbered,
+    /// the `label` is still the id of the figure, and the references to it display
+    /// the caption, or the label if the caption is empty.
+    pub fn step_figure(&mut self, label: Option<String>, caption: &str) -> Option<FigureNumber> {
+        if self.is_enabled(FigureKind::Figure) {
+            return self.step(FigureKind::Figure, label);
+        }
+        let label = label?;
+        let text = match caption.trim() {
+            "" => label.to_string(),
+            caption => caption.to_string(),
+        };
+        self.register(&label, &text);
+        Some(FigureNumber {
+            label: Some(label),
+            text: String::new(),
+        })
+    }
+
+    fn register(&mut self, label: &str, text: &str) {
+        self.labels.insert(label.to_string(), text.to_string());
+    }
+}
+
+/// Split `caption {#label}` to `(caption, label)`.
+pub fn split_label(caption: &str) -> (String, Option<String>) {
+    static RE_LABEL: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"\s*\{#(?<label>[^\s{}]+)\}\s*$"#).unwrap());
+
+    match RE_LABEL.captures(caption) {
+        Some(capture) => {
+            let caption = caption[..capture.get(0).unwrap().start()].to_string();
+            (caption, Some(capture["label"].to_string()))
+        }
+        None => (caption.to_string(), None),
+    }
+}
+
+/// Resolve at the end of parsing an entry:
+/// - the captions of the numbered tables, given by the following paragraph `Table: caption {#label}`,
+/// - the labels of the numbered equations, given by `$$ ... $$ {#label}`,
+/// - and links without text to the labels, e.g. `[](#fig:foo)`, which display the numbers.
+pub fn resolve_figures(
+    content: HTMLContent,
+    counter: &mut FigureCounter,
+    current: &str,
+) -> HTMLContent {
+    static RE_TABLE_CAPTION: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"<figcaption>(?<number>[^<]*)</figcaption></figure>\s*<p>Table:\s*(?<caption>[\s\S]*?)</p>"#)
+            .unwrap()
+    });
+    static RE_EQUATION_LABEL: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"<span class="equation">(?<math>[\s\S]*?<span class="equation-number">(?<number>[^<]*)</span></span>)\s*\{#(?<label>[^\s{}]+)\}"#)
+            .unwrap()
+    });
+    static RE_REFERENCE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r##"<a href="#(?<label>[^"]+)">\s*</a>"##).unwrap());
+
+    let content = map_plain(content, |html| {
+        let html = RE_TABLE_CAPTION.replace_all(html, |capture: &Captures| {
+            let (caption, label) = split_label(&capture["caption"]);
+            let number = FigureNumber {
+                label,
+                text: capture["number"].to_string(),
+            };
+            if let Some(label) = &number.label {
+                counter.register(label, &number.text);
+            }
+            format!("{}</figure>", html_figcaption(caption, Some(&number)))
+        });
+        let html = RE_EQUATION_LABEL.replace_all(&html, |capture: &Captures| {
+            counter.register(&capture["label"], &capture["number"]);
+            format!(
+                r#"<span class="equation" id="{}">{}"#,
+                &capture["label"], &capture["math"]
+            )
+        });
+        html.to_string()
+    });
+
+    map_plain(content, |html| {
+        RE_REFERENCE
+            .replace_all(html, |capture: &Captures| {
+                let label = &capture["label"];
+                match counter.labels.get(label) {
+                    Some(text) => format!(r##"<a href="#{}">{}</a>"##, label, text),
+                    None => {
+                        eprintln!(
+                            "Error: [{}] reference to an unknown label `{}`.",
+                            current, label
+                        );
+                        capture[0].to_string()
+                    }
+                }
+            })
+            .to_string()
+    })
+}
+
+fn map_plain<F>(content: HTMLContent, mut f: F) -> HTMLContent
+where
+    F: FnMut(&str) -> String,
+{
+    match content {
+        HTMLContent::Plain(html) => HTMLContent::Plain(f(&html)),
+        HTMLContent::Lazy(contents) => HTMLContent::Lazy(
+            contents
+                .into_iter()
+                .map(|content| match content {
+                    LazyContent::Plain(html) => LazyContent::Plain(f(&html)),
+                    content => content,
+                })
+                .collect(),
+        ),
+    }
+}
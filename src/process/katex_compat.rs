@@ -1,8 +1,13 @@
 use pulldown_cmark::CowStr;
 
-use crate::recorder::{ParseRecorder, State};
+use crate::{
+    compiler::glossary::isolate_math,
+    config::MathRenderer,
+    html_flake::html_numbered_equation,
+    recorder::{ParseRecorder, State},
+};
 
-use super::processer::Processer;
+use super::{figure::FigureKind, processer::Processer};
 
 pub struct KatexCompact;
 
@@ -23,11 +28,22 @@ impl Processer for KatexCompact {
                 recorder.push(inline_typst);
                 None
             }
-            _ => Some(format!("${}$", formula_disambiguate(&s))),
+            _ if recorder.math == MathRenderer::Typst => None, // see `TypstImage`
+            _ => Some(isolate_math(
+                format!("${}$", formula_disambiguate(s)),
+                recorder,
+            )),
         }
     }
 
-    fn display_math(&self, s: &CowStr<'_>, _recorder: &mut ParseRecorder) -> Option<String> {
-        Some(format!("$${}$$", formula_disambiguate(&s)))
+    fn display_math(&self, s: &CowStr<'_>, recorder: &mut ParseRecorder) -> Option<String> {
+        if recorder.math == MathRenderer::Typst {
+            return None;
+        }
+        let math = isolate_math(format!("$${}$$", formula_disambiguate(s)), recorder);
+        match recorder.figure_counter.step(FigureKind::Equation, None) {
+            Some(number) => Some(html_numbered_equation(&math, &number)),
+            None => Some(math),
+        }
     }
 }
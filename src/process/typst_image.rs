@@ -1,16 +1,20 @@
 use std::fs;
 
 use crate::{
-    compiler::section::{HTMLContent, LazyContent},
-    config::{self, join_path, output_path, parent_dir},
-    html_flake::{html_figure, html_figure_code},
+    compiler::section::{HTMLContent, LazyContent, LazyContents},
+    config::{self, join_path, output_path, parent_dir, MathRenderer},
+    html_flake::{html_figure, html_figure_code, html_numbered_equation},
     recorder::{ParseRecorder, State},
     slug::adjust_name,
-    typst_cli::{self, source_to_inline_html, write_svg, InlineConfig},
+    typst_cli::{self, source_to_inline_html, write_svg, InlineConfig, InlineSettings, Inputs},
+    typst_deps::{self, file_dependencies},
 };
 use pulldown_cmark::{Tag, TagEnd};
 
-use super::processer::{url_action, Processer};
+use super::{
+    figure::{split_label, FigureKind},
+    processer::{url_action, Processer},
+};
 
 pub struct TypstImage;
 
@@ -56,11 +60,13 @@ impl Processer for TypstImage {
                     let typst_url = config::relativize(typst_url);
                     let (parent_dir, filename) = parent_dir(&typst_url);
 
-                    let mut html_url = adjust_name(&filename, ".typ", ".html");
+                    let inputs = figure_inputs(recorder, &typst_url, "");
+                    let variant = typst_cli::variant_hash(&inputs, "");
+                    let html_name = format!(".{:016x}.html", variant);
+                    let mut html_url = adjust_name(&filename, ".typ", &html_name);
                     let img_src = join_path(&parent_dir, &html_url);
                     html_url = output_path(&img_src);
-
-                    let html = match source_to_inline_html(&typst_url, &html_url) {
+                    let html = match source_to_inline_html(&typst_url, &html_url, &inputs) {
                         Ok(inline_html) => inline_html,
                         Err(err) => {
                             eprintln!("{:?} at {}", err, recorder.current);
@@ -72,6 +78,7 @@ impl Processer for TypstImage {
                     return Some(LazyContent::Plain(html));
                 }
                 State::InlineTypst => {
+                    let settings = inline_settings(recorder);
                     let shareds = recorder.shareds.join("\n");
                     let args: Vec<&str> = recorder.data.get(0).unwrap().split("-").collect();
                     let mut args = &args[1..];
@@ -91,15 +98,9 @@ impl Processer for TypstImage {
                     let config = InlineConfig {
                         margin_x: x.map(|s| s.to_string()),
                         margin_y: args.get(1).or(x).map(|s| s.to_string()),
-                        root_dir: config::root_dir(),
-                    };
-                    let html = match typst_cli::source_to_inline_svg(&inline_typst, config) {
-                        Ok(svg) => svg,
-                        Err(err) => {
-                            eprintln!("{:?} at {}", err, recorder.current);
-                            String::new()
-                        }
                     };
+                    let inline_typst = typst_cli::inline_source(&inline_typst, config, &settings);
+                    let html = inline_svg(inline_typst, SvgKind::Inline, recorder);
 
                     recorder.exit();
                     return Some(LazyContent::Plain(html));
@@ -113,17 +114,19 @@ impl Processer for TypstImage {
                     let typst_url = config::relativize(typst_url);
                     let (parent_dir, filename) = parent_dir(&typst_url);
 
-                    let mut svg_url = adjust_name(&filename, ".typ", ".svg");
+                    let preamble = inline_settings(recorder).preamble;
+                    let inputs = figure_inputs(recorder, &typst_url, &preamble);
+                    let variant = typst_cli::variant_hash(&inputs, &preamble);
+                    let svg_name = format!(".{:016x}.svg", variant);
+                    let mut svg_url = adjust_name(&filename, ".typ", &svg_name);
                     let img_src = join_path(&parent_dir, &svg_url);
                     svg_url = output_path(&img_src);
-
-                    match write_svg(&typst_url, &svg_url) {
-                        Err(err) => eprintln!("{:?} at {}", err, recorder.current),
-                        Ok(_) => (),
+                    if let Err(err) = write_svg(&typst_url, &svg_url, &inputs, &preamble) {
+                        eprintln!("{:?} at {}", err, recorder.current);
                     }
                     recorder.exit();
 
-                    let html = html_figure(&config::full_url(&img_src), false, caption);
+                    let html = html_figure(&config::full_url(&img_src), false, caption, None);
                     return Some(LazyContent::Plain(html));
                 }
                 State::ImageBlock => {
@@ -135,17 +138,22 @@ impl Processer for TypstImage {
                     let typst_url = config::relativize(typst_url);
                     let (parent_dir, filename) = parent_dir(&typst_url);
 
-                    let mut svg_url = adjust_name(&filename, ".typ", ".svg");
+                    let preamble = inline_settings(recorder).preamble;
+                    let inputs = figure_inputs(recorder, &typst_url, &preamble);
+                    let variant = typst_cli::variant_hash(&inputs, &preamble);
+                    let svg_name = format!(".{:016x}.svg", variant);
+                    let mut svg_url = adjust_name(&filename, ".typ", &svg_name);
                     let img_src = join_path(&parent_dir, &svg_url);
                     svg_url = output_path(&img_src);
-
-                    match write_svg(&typst_url, &svg_url) {
-                        Err(err) => eprintln!("{:?} at {}", err, recorder.current),
-                        Ok(_) => (),
+                    if let Err(err) = write_svg(&typst_url, &svg_url, &inputs, &preamble) {
+                        eprintln!("{:?} at {}", err, recorder.current);
                     }
                     recorder.exit();
 
-                    let html = html_figure(&config::full_url(&img_src), true, caption);
+                    let (caption, label) = split_label(&caption);
+                    let number = recorder.figure_counter.step_figure(label, &caption);
+                    let html =
+                        html_figure(&config::full_url(&img_src), true, caption, number.as_ref());
                     return Some(LazyContent::Plain(html));
                 }
                 State::ImageCode => {
@@ -157,13 +165,15 @@ impl Processer for TypstImage {
                     let typst_url = config::relativize(typst_url);
                     let (parent_dir, filename) = parent_dir(&typst_url);
 
-                    let mut svg_url = adjust_name(&filename, ".typ", ".svg");
+                    let preamble = inline_settings(recorder).preamble;
+                    let inputs = figure_inputs(recorder, &typst_url, &preamble);
+                    let variant = typst_cli::variant_hash(&inputs, &preamble);
+                    let svg_name = format!(".{:016x}.svg", variant);
+                    let mut svg_url = adjust_name(&filename, ".typ", &svg_name);
                     let img_src = join_path(&parent_dir, &svg_url);
                     svg_url = output_path(&img_src);
-
-                    match write_svg(&typst_url, &svg_url) {
-                        Err(err) => eprintln!("{:?} at {}", err, recorder.current),
-                        Ok(_) => (),
+                    if let Err(err) = write_svg(&typst_url, &svg_url, &inputs, &preamble) {
+                        eprintln!("{:?} at {}", err, recorder.current);
                     }
                     recorder.exit();
 
@@ -172,7 +182,14 @@ impl Processer for TypstImage {
                     let code = fs::read_to_string(format!("{}.code", full_path))
                         .unwrap_or_else(|_| fs::read_to_string(full_path).unwrap());
 
-                    let html = html_figure_code(&config::full_url(&img_src), caption, code);
+                    let (caption, label) = split_label(&caption);
+                    let number = recorder.figure_counter.step_figure(label, &caption);
+                    let html = html_figure_code(
+                        &config::full_url(&img_src),
+                        caption,
+                        code,
+                        number.as_ref(),
+                    );
                     return Some(LazyContent::Plain(html));
                 }
                 State::Shared => {
@@ -189,6 +206,7 @@ impl Processer for TypstImage {
                     recorder
                         .shareds
                         .push(format!(r#"#import "{}": {}"#, typst_url, imported));
+                    config::record_dependencies(file_dependencies(&config::relativize(typst_url)));
                     recorder.exit();
                 }
 
@@ -218,10 +236,27 @@ impl Processer for TypstImage {
     ) -> Option<std::string::String> {
         if allow_inline(&recorder.state) {
             recorder.push(format!("${}$", s)); // [1, 2, ...]: Text
+        } else if recorder.math == MathRenderer::Typst {
+            return Some(typst_math(s, false, recorder));
         }
         None
     }
 
+    fn display_math(
+        &self,
+        s: &pulldown_cmark::CowStr<'_>,
+        recorder: &mut ParseRecorder,
+    ) -> Option<std::string::String> {
+        if recorder.math != MathRenderer::Typst {
+            return None;
+        }
+        let html = typst_math(s, true, recorder);
+        match recorder.figure_counter.step(FigureKind::Equation, None) {
+            Some(number) => Some(html_numbered_equation(&html, &number)),
+            None => Some(html),
+        }
+    }
+
     fn code(&self, s: &pulldown_cmark::CowStr<'_>, recorder: &mut ParseRecorder) {
         if allow_inline(&recorder.state) {
             recorder.push(format!("<code>{}</code>", s));
@@ -229,6 +264,128 @@ impl Processer for TypstImage {
     }
 }
 
+/// Slug of the page being parsed.
+fn current_slug(recorder: &ParseRecorder) -> &str {
+    // `{slug}.md`, or `{slug}.md:metadata` for the metadata values.
+    let current = recorder.current.split(':').next().unwrap_or_default();
+    current.strip_suffix(".md").unwrap_or(current)
+}
+
+/// The `sys.inputs` of the figure in the page being parsed, and none if the figure does
+/// not read them, so that it is compiled once for all pages.
+fn figure_inputs(recorder: &ParseRecorder, typst_path: &str, preamble: &str) -> Inputs {
+    match typst_deps::reads_inputs(typst_path, preamble) {
+        true => typst_cli::page_inputs(current_slug(recorder), recorder.title.as_deref()),
+        false => Inputs::new(),
+    }
+}
+
+fn inline_settings(recorder: &mut ParseRecorder) -> InlineSettings {
+    if recorder.inline_settings.is_none() {
+        let slug = current_slug(recorder).to_string();
+        let settings = InlineSettings::new(&recorder.typst_overrides, &slug);
+        recorder.inline_settings = Some(settings);
+    }
+    recorder.inline_settings.clone().unwrap()
+}
+
+/// How the SVG of an inline Typst is put into the content.
+#[derive(Debug, Clone, Copy)]
+enum SvgKind {
+    /// `[...](inline)`.
+    Inline,
+
+    /// `$...$` with `math: typst`.
+    Math,
+
+    /// `$$...$$` with `math: typst`.
+    DisplayMath,
+}
+
+impl SvgKind {
+    const ALL: [SvgKind; 3] = [SvgKind::Inline, SvgKind::Math, SvgKind::DisplayMath];
+
+    fn html(self, svg: &str) -> String {
+        match self {
+            SvgKind::Inline => typst_cli::inline_svg_html(svg),
+            SvgKind::Math => typst_cli::math_svg_html(svg, false),
+            SvgKind::DisplayMath => typst_cli::math_svg_html(svg, true),
+        }
+    }
+
+    fn placeholder(self, index: usize) -> String {
+        let name = match self {
+            SvgKind::Inline => "inline-typst",
+            SvgKind::Math => "math-typst",
+            SvgKind::DisplayMath => "display-typst",
+        };
+        format!("<!-- {}:{} -->", name, index)
+    }
+}
+
+/// The HTML of the inline source if its SVG is cached, or a placeholder which is
+/// replaced by [`resolve_inline_typsts`] once the pending sources are compiled.
+fn inline_svg(inline_typst: String, kind: SvgKind, recorder: &mut ParseRecorder) -> String {
+    match typst_cli::cached_inline_svg(&inline_typst) {
+        (_, Some(svg)) => kind.html(&svg),
+        (svg_path, None) => {
+            let pendings = &mut recorder.inline_typsts;
+            let index = match pendings.iter().position(|(p, _)| *p == svg_path) {
+                Some(index) => index,
+                None => {
+                    pendings.push((svg_path, inline_typst));
+                    pendings.len() - 1
+                }
+            };
+            kind.placeholder(index)
+        }
+    }
+}
+
+/// Markdown math in Typst syntax, with the shared imports and the inline settings.
+fn typst_math(formula: &str, display: bool, recorder: &mut ParseRecorder) -> String {
+    let settings = inline_settings(recorder);
+    let source = format!(
+        "{}\n{}",
+        recorder.shareds.join("\n"),
+        typst_cli::math_source(formula, display)
+    );
+    let source = typst_cli::inline_source(&source, InlineConfig::new(), &settings);
+    let kind = match display {
+        true => SvgKind::DisplayMath,
+        false => SvgKind::Math,
+    };
+    inline_svg(source, kind, recorder)
+}
+
+/// Compile the pending inline Typst of the content at once, and replace their placeholders.
+pub fn resolve_inline_typsts(contents: &mut LazyContents, recorder: &mut ParseRecorder) {
+    let pendings = std::mem::take(&mut recorder.inline_typsts);
+    if pendings.is_empty() {
+        return;
+    }
+
+    let svgs = match typst_cli::compile_inline_svgs(&pendings, &config::root_dir()) {
+        Ok(svgs) => svgs,
+        Err(err) => {
+            eprintln!("{:?} at {}", err, recorder.current);
+            vec![String::new(); pendings.len()]
+        }
+    };
+    for content in contents.iter_mut() {
+        if let LazyContent::Plain(html) = content {
+            for (index, svg) in svgs.iter().enumerate() {
+                for kind in SvgKind::ALL {
+                    let placeholder = kind.placeholder(index);
+                    if html.contains(&placeholder) {
+                        *html = html.replace(&placeholder, &kind.html(svg));
+                    }
+                }
+            }
+        }
+    }
+}
+
 fn allow_inline(state: &State) -> bool {
     *state == State::Shared
         || *state == State::InlineTypst
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
-use crate::process::processer::FootnoteCounter;
+use crate::config::{self, MathRenderer};
+use crate::process::{figure::FigureCounter, processer::FootnoteCounter};
+use crate::typst_cli::InlineSettings;
 
 
 #[derive(Debug, PartialEq)]
@@ -9,6 +11,15 @@ pub enum State {
     None,
     Embed,
 
+    /// Query block listing or embedding matching entries
+    Query,
+
+    /// Citations `[@key]` to the bibliography
+    Citation,
+
+    /// Text `[term](#:nolink)` excluded from the glossary autolinking
+    NoLink,
+
     /// Shared for inline typst
     Shared,
 
@@ -38,6 +49,9 @@ impl State {
         match self {
             State::None => "none",
             State::Embed => "embed",
+            State::Query => "query",
+            State::Citation => "citation",
+            State::NoLink => "nolink", // style class name
             State::Shared => "shared",
             State::Html => "html", 
             State::InlineTypst => "inline",
@@ -58,7 +72,25 @@ pub struct ParseRecorder {
     pub current: String,
     pub data: Vec<String>,
     pub shareds: Vec<String>,
-    pub footnote_counter: FootnoteCounter
+
+    /// Inline Typst `(svg_path, source)` to be compiled together when the content is parsed.
+    pub inline_typsts: Vec<(String, String)>,
+
+    /// The `title` metadata in its source, passed to the Typst figures by `sys.inputs`.
+    pub title: Option<String>,
+
+    /// The `typst-{key}` metadata by the key, see [`InlineSettings`].
+    pub typst_overrides: HashMap<String, String>,
+    pub inline_settings: Option<InlineSettings>,
+
+    /// Renderer of the Markdown math, by the `math` metadata or `kodama.toml`.
+    pub math: MathRenderer,
+
+    /// Whether the glossary links the terms of the page, by the `autolink` metadata
+    /// or `kodama.toml`, see [`crate::compiler::glossary::isolate_math`].
+    pub autolink: bool,
+    pub footnote_counter: FootnoteCounter,
+    pub figure_counter: FigureCounter,
 }
 
 impl ParseRecorder {
@@ -68,10 +100,29 @@ impl ParseRecorder {
             current,
             data: vec![],
             shareds: vec![],
+            inline_typsts: vec![],
+            title: None,
+            typst_overrides: HashMap::new(),
+            inline_settings: None,
+            math: config::project_config().math,
+            autolink: config::project_config().glossary.autolink,
             footnote_counter: HashMap::new(), 
+            figure_counter: FigureCounter::default(),
         };
     }
 
+    /// The recorder of the metadata values of the page, with the settings of the page,
+    /// such as `math` and `typst-{key}`.
+    pub fn metadata_recorder(&self) -> ParseRecorder {
+        let mut recorder = ParseRecorder::new(format!("{}:metadata", self.current));
+        recorder.title = self.title.clone();
+        recorder.typst_overrides = self.typst_overrides.clone();
+        recorder.inline_settings = self.inline_settings.clone();
+        recorder.math = self.math;
+        recorder.autolink = false;
+        recorder
+    }
+
     pub fn enter(&mut self, form: State) {
         self.state = form;
     }
@@ -1,12 +1,61 @@
-use std::{fs, path::Path, process::Command};
+use std::{collections::HashMap, fs, path::Path, process::Command};
+
+use eyre::{eyre, WrapErr};
 
 use crate::{
-    config::{self, verify_and_file_hash},
+    config,
+    entry::KEY_TYPST_PREFIX,
     html, html_flake,
+    typst_deps::{self, verify_file_dependencies},
 };
 
-pub fn source_to_inline_html(typst_path: &str, html_path: &str) -> Result<String, std::io::Error> {
-    if !verify_and_file_hash(typst_path)? && Path::new(html_path).exists() {
+#[cfg(feature = "embed-typst")]
+use crate::typst_lib::{self, Main};
+
+/// `sys.inputs` of a Typst file compiled for a page, see [`page_inputs`].
+pub type Inputs = Vec<(&'static str, String)>;
+
+/// The context of the page a Typst file is compiled for, e.g. `sys.inputs.slug`.
+pub fn page_inputs(slug: &str, title: Option<&str>) -> Inputs {
+    let profile = match config::is_drafts() {
+        true => "dev",
+        false => "release",
+    };
+    let mut inputs = vec![
+        ("slug", slug.to_string()),
+        ("base-url", config::base_url()),
+        ("url", config::full_html_url(slug)),
+        ("theme", config::project_config().typst.theme.clone()),
+        ("profile", profile.to_string()),
+    ];
+    if let Some(title) = title {
+        inputs.push(("title", title.to_string()));
+    }
+    inputs
+}
+
+/// Hash of the inputs and the preamble a Typst file is compiled with, which names its
+/// output, so that a file compiled differently for several pages has an output for
+/// each of them. See [`typst_deps::reads_inputs`].
+pub fn variant_hash(inputs: &Inputs, preamble: &str) -> u64 {
+    config::stable_hash(format!("{:?}\0{}", inputs, preamble).as_bytes())
+}
+
+fn input_args(inputs: &Inputs) -> Vec<String> {
+    inputs
+        .iter()
+        .map(|(key, value)| format!("--input={}={}", key, value))
+        .collect()
+}
+
+pub fn source_to_inline_html(
+    typst_path: &str,
+    html_path: &str,
+    inputs: &Inputs,
+) -> Result<String, std::io::Error> {
+    if !verify_file_dependencies(typst_path, html_path, inputs, "")?
+        && Path::new(html_path).exists()
+    {
         let existed_html = fs::read_to_string(html_path)?;
         let existed_html = html_to_body_content(&existed_html);
         println!("Skip: {}", crate::slug::pretty_path(Path::new(typst_path)));
@@ -14,8 +63,7 @@ pub fn source_to_inline_html(typst_path: &str, html_path: &str) -> Result<String
     }
 
     let root_dir = config::root_dir();
-    let full_path = config::join_path(&root_dir, typst_path);
-    let html = source_to_html(&full_path, &root_dir)?;
+    let html = file_to_full_html(typst_path, &root_dir, inputs)?;
     let html_body = html_to_body_content(&html);
 
     fs::write(html_path, html)?;
@@ -37,47 +85,327 @@ pub fn html_to_body_content(html: &str) -> String {
 pub struct InlineConfig {
     pub margin_x: Option<String>,
     pub margin_y: Option<String>,
-    pub root_dir: String,
 }
 
 impl InlineConfig {
-    #[allow(dead_code)]
     pub fn new() -> InlineConfig {
         InlineConfig {
             margin_x: None,
             margin_y: None,
-            root_dir: config::root_dir(),
         }
     }
+}
+
+/// Settings of the inline Typst in a page, by `[typst.inline]` in `kodama.toml`
+/// overridden by the `typst-{key}` metadata of the page.
+#[derive(Debug, Clone)]
+pub struct InlineSettings {
+    /// Content of the preamble file, with its paths made absolute by [`typst_deps::rebase`].
+    pub preamble: String,
+    pub text_size: String,
+    pub font: Option<String>,
+    pub top_edge: String,
+    pub bottom_edge: String,
+    pub margin: String,
+}
+
+impl InlineSettings {
+    pub fn new(overrides: &HashMap<String, String>, slug: &str) -> InlineSettings {
+        let inline = &config::project_config().typst.inline;
+        let mut preamble = inline.preamble.clone();
+        let mut settings = InlineSettings {
+            preamble: String::new(),
+            text_size: inline.text_size.clone(),
+            font: inline.font.clone(),
+            top_edge: inline.top_edge.clone(),
+            bottom_edge: inline.bottom_edge.clone(),
+            margin: inline.margin.clone(),
+        };
+        for (key, value) in overrides {
+            let value = value.to_string();
+            match key.as_str() {
+                "preamble" => preamble = Some(value),
+                "text-size" => settings.text_size = value,
+                "font" => settings.font = Some(value),
+                "top-edge" => settings.top_edge = value,
+                "bottom-edge" => settings.bottom_edge = value,
+                "margin" => settings.margin = value,
+                _ => eprintln!("Warning: [{slug}] unknown metadata `{KEY_TYPST_PREFIX}{key}`."),
+            }
+        }
+
+        // `typst-preamble: none` disables the preamble of `kodama.toml` in the page.
+        if let Some(path) = preamble.filter(|path| !path.is_empty() && path != "none") {
+            let full_path = config::join_path(&config::root_dir(), &path);
+            match fs::read_to_string(&full_path) {
+                Ok(preamble) => {
+                    let path = typst_deps::normalize(Path::new(&path));
+                    let base_dir = Path::new(&path).parent().unwrap_or(Path::new(""));
+                    settings.preamble = typst_deps::rebase(&preamble, base_dir);
+                    config::record_dependencies(typst_deps::file_dependencies(&path));
+                }
+                Err(err) => {
+                    eprintln!("Error: [{slug}] failed to read the Typst preamble `{path}`: {err}")
+                }
+            }
+        }
+        settings
+    }
+}
 
-    pub fn default_margin() -> String {
-        "0em".to_string()
+/// A text metric such as `bounds` as a string, and a length as it is.
+fn text_edge(edge: &str) -> String {
+    match edge.chars().all(|c| c.is_ascii_alphabetic() || c == '-') {
+        true => format!("\"{}\"", edge),
+        false => edge.to_string(),
     }
 }
 
-pub fn source_to_inline_svg(src: &str, config: InlineConfig) -> Result<String, std::io::Error> {
+/// The standalone Typst document of an inline snippet, which is also the key of its
+/// cached SVG.
+pub fn inline_source(src: &str, config: InlineConfig, settings: &InlineSettings) -> String {
+    let font = match &settings.font {
+        Some(font) => format!(", font: \"{}\"", font),
+        None => String::new(),
+    };
     let styles = format!(
         r#"
 #set page(width: auto, height: auto, margin: (x: {}, y: {}), fill: rgb(0, 0, 0, 0)); 
-#set text(size: 15.427pt, top-edge: "bounds", bottom-edge: "bounds");
+#set text(size: {}, top-edge: {}, bottom-edge: {}{});
     "#,
-        config.margin_x.unwrap_or(InlineConfig::default_margin()),
-        config.margin_y.unwrap_or(InlineConfig::default_margin())
+        config.margin_x.unwrap_or(settings.margin.clone()),
+        config.margin_y.unwrap_or(settings.margin.clone()),
+        settings.text_size,
+        text_edge(&settings.top_edge),
+        text_edge(&settings.bottom_edge),
+        font
     );
-    let svg = source_to_svg(format!("{}{}", styles, src).as_str(), &config.root_dir)?;
+    format!("{}{}\n{}", styles, settings.preamble, src)
+}
+
+/// Path of the cached SVG of the inline source, and the SVG if it has been compiled.
+pub fn cached_inline_svg(src: &str) -> (String, Option<String>) {
+    // Compiled from the buffer file in the cache directory.
+    let dependencies = typst_deps::source_dependencies(src, config::CACHE_DIR_NAME);
+    let dependencies_hash = typst_deps::dependencies_hash(&dependencies);
+    let key = config::stable_hash(format!("{}\0{:016x}", src, dependencies_hash).as_bytes());
+    config::record_dependencies(dependencies);
+
+    let svg_path = config::inline_path(key);
+    let svg = fs::read_to_string(&svg_path).ok();
+    (svg_path, svg)
+}
+
+pub fn inline_svg_html(svg: &str) -> String {
+    format!("\n{}\n", html!(span class = "inline-typst" => {svg}))
+}
+
+/// Typst source of the Markdown math `$formula$`, or `$$formula$$` if `display`.
+/// An inline formula is padded to have its baseline at the middle of the SVG,
+/// so that [`math_svg_html`] can align the baseline to the text.
+pub fn math_source(formula: &str, display: bool) -> String {
+    if display {
+        return format!("$ {} $", formula);
+    }
+    // Typst lets an inline formula overflow its line by a part of the leading,
+    // which would be clipped. The depth is what it adds under a strut on the baseline.
+    format!(
+        r#"#{{
+  set par(leading: 0pt)
+  let formula = ${}$
+  context {{
+    let height = measure(formula).height
+    let depth = calc.max(measure([#box(height: 1000pt)#formula]).height - 1000pt, 0pt)
+    let pad = height - 2 * depth
+    box(inset: (top: calc.max(-pad, 0pt), bottom: calc.max(pad, 0pt)), formula)
+  }}
+}}"#,
+        formula
+    )
+}
+
+/// The compiled SVG of [`math_source`].
+pub fn math_svg_html(svg: &str, display: bool) -> String {
+    if display {
+        return format!("\n{}\n", html!(span class = "display-typst" => {svg}));
+    }
+    let html = match svg_height(svg) {
+        Some(height) => {
+            let style = format!("vertical-align: -{:.3}pt", height / 2.0);
+            html!(span class = "inline-typst", style = {style} => {svg})
+        }
+        None => html!(span class = "inline-typst" => {svg}),
+    };
+    format!("\n{}\n", html)
+}
+
+/// Height in `pt` of the SVG exported by Typst, i.e. `<svg ... height="12.5pt">`.
+fn svg_height(svg: &str) -> Option<f64> {
+    let tag = &svg[svg.find("<svg")?..];
+    let tag = &tag[..tag.find('>')?];
+    let height = &tag[tag.find(" height=\"")? + 9..];
+    height[..height.find('"')?].strip_suffix("pt")?.parse().ok()
+}
+
+/// Compile the inline sources with one Typst process, one page for each source,
+/// and cache the SVGs at the paths. If the pages cannot be matched to the sources,
+/// e.g. one of the sources fails, they are compiled one by one instead.
+pub fn compile_inline_svgs(
+    sources: &[(String, String)],
+    root_dir: &str,
+) -> Result<Vec<String>, std::io::Error> {
+    let svgs = match sources.len() {
+        0 => vec![],
+        1 => vec![source_to_svg(&sources[0].1, root_dir)?],
+        _ => match compile_pages(sources, root_dir)? {
+            Some(svgs) => svgs,
+            None => sources
+                .iter()
+                .map(|(_, src)| source_to_svg(src, root_dir))
+                .collect::<Result<_, _>>()?,
+        },
+    };
+
+    for ((svg_path, _), svg) in sources.iter().zip(&svgs) {
+        if !svg.is_empty() {
+            fs::write(svg_path, svg)?;
+        }
+    }
+    Ok(svgs)
+}
+
+/// Each source is scoped in a content block and starts a new page.
+fn compile_pages(
+    sources: &[(String, String)],
+    root_dir: &str,
+) -> Result<Option<Vec<String>>, std::io::Error> {
+    let document = sources
+        .iter()
+        .map(|(_, src)| format!("#[\n{}\n]\n", src))
+        .collect::<Vec<_>>()
+        .join("#pagebreak(weak: true)\n");
+
+    // The diagnostics are reported when the sources are compiled one by one.
+    #[cfg(feature = "embed-typst")]
+    {
+        let main = Main::Source(&buffer_source(), &document);
+        match typst_lib::compile_svgs(main, &Inputs::new(), false) {
+            Some(Ok(svgs)) => return Ok((svgs.len() == sources.len()).then_some(svgs)),
+            Some(Err(_)) => return Ok(None),
+            None => (),
+        }
+    }
+
+    let buffer_path = config::buffer_path();
+    let pages_dir = config::join_path(&config::get_cache_dir(), config::PAGES_DIR_NAME);
+    if Path::new(&pages_dir).exists() {
+        fs::remove_dir_all(&pages_dir)?;
+    }
+    fs::create_dir_all(&pages_dir)?;
+    fs::write(&buffer_path, document)?;
+
+    let output = typst_command("svg", root_dir)
+        .arg(&buffer_path)
+        .arg(config::join_path(&pages_dir, "{p}.svg"))
+        .output()?;
+    fs::remove_file(buffer_path)?;
 
-    Ok(format!(
-        "\n{}\n",
-        html!(span class = "inline-typst" => {svg})
-    ))
+    let page_path = |page: usize| config::join_path(&pages_dir, &format!("{}.svg", page));
+    let is_matched = !Path::new(&page_path(sources.len() + 1)).exists();
+    let svgs = match output.status.success() && is_matched {
+        true => (1..=sources.len())
+            .map(|page| fs::read_to_string(page_path(page)).ok())
+            .collect(),
+        false => None,
+    };
+    fs::remove_dir_all(&pages_dir)?;
+    Ok(svgs)
 }
 
-pub fn source_to_html(full_path: &str, root_dir: &str) -> Result<String, std::io::Error> {
-    let output = Command::new("typst")
+/// `typst compile` to the format with the toolchain configured by `[typst]` in `kodama.toml`.
+fn typst_command(output_format: &str, root_dir: &str) -> Command {
+    let typst = &config::project_config().typst;
+    let mut command = Command::new(typst_executable());
+    command
         .arg("c")
-        .arg("-f=html")
-        .arg(format!("--root={}", root_dir))
+        .arg(format!("-f={}", output_format))
+        .arg(format!("--root={}", root_dir));
+    for font_path in &typst.font_paths {
+        let path = config::join_path(root_dir, font_path);
+        command.arg(format!("--font-path={}", path));
+    }
+    if typst.ignore_system_fonts {
+        command.arg("--ignore-system-fonts");
+    }
+    if let Some(package_path) = &typst.package_path {
+        let path = config::join_path(root_dir, package_path);
+        command.arg(format!("--package-path={}", path));
+    }
+    if let Some(package_cache_path) = &typst.package_cache_path {
+        let path = config::join_path(root_dir, package_cache_path);
+        command.arg(format!("--package-cache-path={}", path));
+    }
+    command.args(&typst.args);
+    command
+}
+
+/// The configured executable, resolved against the project root if it is a path.
+fn typst_executable() -> String {
+    let executable = &config::project_config().typst.executable;
+    match executable.contains(['/', '\\']) && Path::new(executable).is_relative() {
+        true => config::join_path(&config::root_dir(), executable),
+        false => executable.to_string(),
+    }
+}
+
+/// Check the version of the Typst executable against `typst.min-version`, if any.
+pub fn check_version() -> eyre::Result<()> {
+    let Some(min_version) = &config::project_config().typst.min_version else {
+        return Ok(());
+    };
+    let required = parse_version(min_version)
+        .ok_or_else(|| eyre!("invalid `typst.min-version` `{min_version}` in kodama.toml"))?;
+
+    let executable = typst_executable();
+    let output = Command::new(&executable)
+        .arg("--version")
+        .output()
+        .wrap_err_with(|| eyre!("failed to run the Typst executable `{executable}`"))?;
+    // e.g. `typst 0.13.1 (8ace67d9)`
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let found = stdout.split_whitespace().nth(1).unwrap_or_default();
+    match parse_version(found) {
+        Some(version) if version >= required => Ok(()),
+        Some(_) => Err(eyre!(
+            "Typst {found} at `{executable}` is older than the required {min_version}"
+        )),
+        None => Err(eyre!(
+            "failed to read the version of the Typst executable `{executable}`: {}",
+            stdout.trim()
+        )),
+    }
+}
+
+/// `0.13.1` to `[0, 13, 1]`, where the missing parts are zero. Pre-releases such as
+/// `0.13.0-rc1` count as their release.
+fn parse_version(version: &str) -> Option<[u32; 3]> {
+    let mut parts = [0; 3];
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    for (index, part) in version.split('.').enumerate() {
+        *parts.get_mut(index)? = part.parse().ok()?;
+    }
+    Some(parts)
+}
+
+pub fn source_to_html(
+    full_path: &str,
+    root_dir: &str,
+    inputs: &Inputs,
+) -> Result<String, std::io::Error> {
+    let output = typst_command("html", root_dir)
         .arg("--features=html")
+        .args(input_args(inputs))
         .arg(&full_path)
         .arg("-")
         .stdout(std::process::Stdio::piped())
@@ -94,6 +422,18 @@ pub fn source_to_html(full_path: &str, root_dir: &str) -> Result<String, std::io
 }
 
 pub fn source_to_svg(src: &str, root_dir: &str) -> Result<String, std::io::Error> {
+    #[cfg(feature = "embed-typst")]
+    {
+        let main = Main::Source(&buffer_source(), src);
+        match typst_lib::compile_svgs(main, &Inputs::new(), true) {
+            Some(Ok(svgs)) => return Ok(svgs.into_iter().next().unwrap_or_default()),
+            Some(Err(errors)) => {
+                typst_lib::print_errors(&errors);
+                return Ok(String::new());
+            }
+            None => (),
+        }
+    }
     compile_source(src, root_dir, "svg")
 }
 
@@ -106,10 +446,7 @@ pub fn compile_source(
     let buffer_path = config::buffer_path();
     fs::write(&buffer_path, src)?;
 
-    let output = Command::new("typst")
-        .arg("c")
-        .arg(format!("-f={}", output_format))
-        .arg(format!("--root={}", root_dir))
+    let output = typst_command(output_format, root_dir)
         .arg(&buffer_path)
         .arg("-")
         .stdout(std::process::Stdio::piped())
@@ -130,54 +467,111 @@ pub fn compile_source(
     })
 }
 
-pub fn file_to_html(typst_path: &str, root_dir: &str) -> Result<String, std::io::Error> {
-    compile_file(typst_path, root_dir, "html", Some("--features=html"))
-        .map(|s| html_to_body_content(&s))
+/// Path of the buffer file relative to the project root, whose content is compiled
+/// by [`typst_lib`] without writing it.
+#[cfg(feature = "embed-typst")]
+fn buffer_source() -> String {
+    config::join_path(config::CACHE_DIR_NAME, config::BUFFER_FILE_NAME)
 }
 
-pub fn compile_file(
+pub fn file_to_html(
     typst_path: &str,
     root_dir: &str,
-    output_format: &str,
-    extra: Option<&str>,
+    inputs: &Inputs,
 ) -> Result<String, std::io::Error> {
-    let output = Command::new("typst")
-        .arg("c")
-        .arg(format!("-f={}", output_format))
-        .arg(format!("--root={}", root_dir))
-        .args(extra)
-        .arg(typst_path.to_string())
-        .arg("-")
-        .stdout(std::process::Stdio::piped())
-        .output()?;
+    file_to_full_html(typst_path, root_dir, inputs).map(|s| html_to_body_content(&s))
+}
 
-    Ok(if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        stdout.to_string()
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        failed_in_file(concat!(file!(), '#', line!()), &typst_path, stderr);
-        String::new()
-    })
+/// The HTML document of the Typst file relative to the project root.
+fn file_to_full_html(
+    typst_path: &str,
+    root_dir: &str,
+    inputs: &Inputs,
+) -> Result<String, std::io::Error> {
+    #[cfg(feature = "embed-typst")]
+    match typst_lib::compile_html(Main::File(typst_path), inputs) {
+        Some(Ok(html)) => return Ok(html),
+        Some(Err(errors)) => {
+            typst_lib::print_errors(&errors);
+            return Err(std::io::Error::other(format!(
+                "failed to compile `{typst_path}`"
+            )));
+        }
+        None => (),
+    }
+    let full_path = config::join_path(root_dir, typst_path);
+    source_to_html(&full_path, root_dir, inputs)
 }
 
-/// typst file to svg (`stdout -> disk`)
-pub fn write_svg(typst_path: &str, svg_path: &str) -> Result<(), std::io::Error> {
-    if !verify_and_file_hash(typst_path)? && Path::new(svg_path).exists() {
+/// typst file to svg (`stdout -> disk`). With a preamble, the file is included by
+/// the preamble, so that the set and show rules of the preamble apply to it.
+pub fn write_svg(
+    typst_path: &str,
+    svg_path: &str,
+    inputs: &Inputs,
+    preamble: &str,
+) -> Result<(), std::io::Error> {
+    if !verify_file_dependencies(typst_path, svg_path, inputs, preamble)?
+        && Path::new(svg_path).exists()
+    {
         println!("Skip: {}", crate::slug::pretty_path(Path::new(typst_path)));
         return Ok(());
     }
 
+    let wrapper = (!preamble.is_empty()).then(|| {
+        let path = typst_deps::normalize(Path::new(typst_path));
+        format!("{}\n#include \"/{}\"\n", preamble, path)
+    });
+
+    #[cfg(feature = "embed-typst")]
+    let main = match &wrapper {
+        Some(wrapper) => Main::Source(&buffer_source(), wrapper),
+        None => Main::File(typst_path),
+    };
+    #[cfg(feature = "embed-typst")]
+    match typst_lib::compile_svgs(main, inputs, true) {
+        Some(Ok(svgs)) => {
+            let Some(svg) = svgs.first() else {
+                let _ = fs::remove_file(svg_path);
+                return Err(std::io::Error::other(format!(
+                    "`{typst_path}` has no pages to export as SVG"
+                )));
+            };
+            fs::write(svg_path, thematize(svg.into()))?;
+            println!(
+                "Compiled to SVG: {}",
+                crate::slug::pretty_path(Path::new(svg_path))
+            );
+            return Ok(());
+        }
+        Some(Err(errors)) => {
+            // Like a failed command, the errors are reported and the stale SVG
+            // is removed so that the next build compiles the file again.
+            typst_lib::print_errors(&errors);
+            let _ = fs::remove_file(svg_path);
+            return Ok(());
+        }
+        None => (),
+    }
+
     let root_dir = config::root_dir();
     let full_path = config::join_path(&root_dir, typst_path);
-    let output = Command::new("typst")
-        .arg("c")
-        .arg("-f=svg")
-        .arg(format!("--root={}", root_dir))
-        .arg(&full_path)
+    let main_path = match &wrapper {
+        Some(wrapper) => {
+            fs::write(config::buffer_path(), wrapper)?;
+            config::buffer_path()
+        }
+        None => full_path.clone(),
+    };
+    let output = typst_command("svg", &root_dir)
+        .args(input_args(inputs))
+        .arg(&main_path)
         .arg("-")
         .stdout(std::process::Stdio::piped())
         .output()?;
+    if wrapper.is_some() {
+        fs::remove_file(main_path)?;
+    }
 
     if output.status.success() {
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -191,6 +585,7 @@ pub fn write_svg(typst_path: &str, svg_path: &str) -> Result<(), std::io::Error>
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
         failed_in_file(concat!(file!(), '#', line!()), &full_path, stderr);
+        let _ = fs::remove_file(svg_path);
     }
     Ok(())
 }
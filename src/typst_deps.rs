@@ -0,0 +1,292 @@
+//! Dependencies of Typst sources, found by scanning the sources for the files they
+//! import, include or read, e.g. `#import "/lib.typ"` or `image("plot.png")`.
+//! Packages such as `@preview/cetz:0.3.0` are versioned and thus not tracked, but the
+//! `@local` ones are edited in place, so their directories are tracked, as are the
+//! directories in `typst.font-paths`. Paths computed by the source, e.g.
+//! `read(name + ".csv")`, cannot be found by scanning and are not tracked.
+
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+    sync::LazyLock,
+    time::UNIX_EPOCH,
+};
+
+use regex_lite::{Captures, Regex};
+use walkdir::WalkDir;
+
+use crate::{config, typst_cli::Inputs};
+
+static RE_DEPENDENCY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r#"\b(?:import|include)\s+"(?<module>[^"]+)""#,
+        r#"|\b(?:image|read|json|csv|yaml|toml|xml|cbor|bibliography|plugin)\(\s*"(?<file>[^"]+)""#
+    ))
+    .unwrap()
+});
+
+/// The files the Typst file depends on, including itself, transitively, and the font
+/// directories. Paths are relative to the project root, or absolute outside it.
+pub fn file_dependencies(typst_path: &str) -> BTreeSet<String> {
+    let mut dependencies = font_dependencies();
+    collect(&normalize(Path::new(typst_path)), &mut dependencies);
+    dependencies
+}
+
+/// The files the Typst source `src` in the directory `base_dir` depends on, transitively,
+/// and the font directories.
+pub fn source_dependencies(src: &str, base_dir: &str) -> BTreeSet<String> {
+    let mut dependencies = font_dependencies();
+    for path in scan(src, Path::new(base_dir)) {
+        collect(&path, &mut dependencies);
+    }
+    dependencies
+}
+
+fn font_dependencies() -> BTreeSet<String> {
+    let typst = &config::project_config().typst;
+    typst
+        .font_paths
+        .iter()
+        .map(|path| configured_path(Path::new(path)))
+        .collect()
+}
+
+/// A path configured in `[typst]`, relative to the project root unless it is absolute.
+fn configured_path(path: &Path) -> String {
+    match path.is_absolute() {
+        true => path.to_str().unwrap().replace("\\", "/"),
+        false => normalize(path),
+    }
+}
+
+fn collect(path: &str, dependencies: &mut BTreeSet<String>) {
+    let is_typst = path.ends_with(".typ") || path.ends_with(".typst");
+    if !dependencies.insert(path.to_string()) || !is_typst {
+        return;
+    }
+    let Ok(src) = std::fs::read_to_string(config::join_path(&config::root_dir(), path)) else {
+        return;
+    };
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    for dependency in scan(&src, base_dir) {
+        collect(&dependency, dependencies);
+    }
+}
+
+/// Paths in `src` resolved against `base_dir`, or the project root if starting with `/`,
+/// and the directories of the `@local` packages found.
+fn scan(src: &str, base_dir: &Path) -> Vec<String> {
+    RE_DEPENDENCY
+        .captures_iter(src)
+        .filter_map(|captures| captures.name("module").or(captures.name("file")))
+        .map(|m| m.as_str())
+        .filter_map(|path| match path.strip_prefix('@') {
+            Some(spec) => local_package_dir(spec),
+            None => match path.strip_prefix('/') {
+                Some(path) => Some(normalize(Path::new(path))),
+                None => Some(normalize(&base_dir.join(path))),
+            },
+        })
+        .collect()
+}
+
+/// The directory of the package `local/{name}:{version}`, if it is a local one.
+fn local_package_dir(spec: &str) -> Option<String> {
+    let (name, version) = spec.strip_prefix("local/")?.split_once(':')?;
+    package_dir("local", name, version)
+}
+
+/// The directory of the package in `typst.package-path` or `typst.package-cache-path`,
+/// relative to the project root, or downloaded by the `typst` CLI, if any.
+pub fn package_dir(namespace: &str, name: &str, version: &str) -> Option<String> {
+    let subdir = format!("{}/{}/{}", namespace, name, version);
+    let typst = &config::project_config().typst;
+    let root_dir = config::root_dir();
+    let configured = [&typst.package_path, &typst.package_cache_path]
+        .into_iter()
+        .flatten()
+        .map(|path| configured_path(&Path::new(path).join(&subdir)))
+        .filter(|dir| Path::new(&config::join_path(&root_dir, dir)).is_dir());
+
+    let home = std::env::var("HOME").ok().map(PathBuf::from);
+    let env_dir = |name: &str| std::env::var(name).ok().map(PathBuf::from);
+    let defaults = [
+        env_dir("XDG_DATA_HOME").or(home.as_ref().map(|h| h.join(".local/share"))),
+        env_dir("XDG_CACHE_HOME").or(home.as_ref().map(|h| h.join(".cache"))),
+        home.as_ref().map(|h| h.join("Library/Application Support")),
+        home.as_ref().map(|h| h.join("Library/Caches")),
+        env_dir("APPDATA"),
+        env_dir("LOCALAPPDATA"),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|dir| dir.join("typst/packages").join(&subdir))
+    .filter(|dir| dir.is_dir())
+    .map(|dir| dir.to_str().unwrap().replace("\\", "/"));
+
+    configured.chain(defaults).next()
+}
+
+/// `src` of a file in the directory `base_dir` with its relative paths made absolute,
+/// i.e. starting with `/` for the project root, so that it can be compiled elsewhere.
+pub fn rebase(src: &str, base_dir: &Path) -> String {
+    RE_DEPENDENCY
+        .replace_all(src, |captures: &Captures| {
+            let whole = captures.get(0).unwrap();
+            let path = captures.name("module").or(captures.name("file")).unwrap();
+            if path.as_str().starts_with(['@', '/']) {
+                return whole.as_str().to_string();
+            }
+            format!(
+                "{}/{}{}",
+                &src[whole.start()..path.start()],
+                normalize(&base_dir.join(path.as_str())),
+                &src[path.end()..whole.end()]
+            )
+        })
+        .into_owned()
+}
+
+/// Resolve `.` and `..` lexically, with `/` as the separator.
+pub fn normalize(path: &Path) -> String {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => (),
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(".."),
+            },
+            Component::Normal(name) => normalized.push(name),
+        }
+    }
+    normalized.to_str().unwrap().replace("\\", "/")
+}
+
+/// Stable hash of the contents of the dependencies. A missing file is hashed as empty.
+/// A directory, e.g. of fonts, is hashed by the paths, sizes and modification times of
+/// its files, which is enough to notice a change without reading every font.
+pub fn dependencies_hash(dependencies: &BTreeSet<String>) -> u64 {
+    let root_dir = config::root_dir();
+    let lines: Vec<String> = dependencies
+        .iter()
+        .map(|path| {
+            let full_path = config::join_path(&root_dir, path);
+            let content = match Path::new(&full_path).is_dir() {
+                true => directory_listing(&full_path).into_bytes(),
+                false => std::fs::read(&full_path).unwrap_or_default(),
+            };
+            format!("{} {:016x}", path, config::stable_hash(&content))
+        })
+        .collect();
+    config::stable_hash(lines.join("\n").as_bytes())
+}
+
+fn directory_listing(dir: &str) -> String {
+    let mut lines = vec![];
+    for entry in WalkDir::new(dir).follow_links(true).sort_by_file_name() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_nanos());
+        let path = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        lines.push(format!(
+            "{} {} {}",
+            path.display(),
+            metadata.len(),
+            modified
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Whether the Typst file, the preamble or any file they use reads `sys.inputs`, so that
+/// the file is compiled for each page using it. The packages are not scanned.
+pub fn reads_inputs(typst_path: &str, preamble: &str) -> bool {
+    let mut dependencies = file_dependencies(typst_path);
+    dependencies.extend(source_dependencies(preamble, ""));
+    let root_dir = config::root_dir();
+    preamble.contains("sys.inputs")
+        || dependencies
+            .iter()
+            .filter(|path| path.ends_with(".typ") || path.ends_with(".typst"))
+            .filter_map(|path| std::fs::read_to_string(config::join_path(&root_dir, path)).ok())
+            .any(|src| src.contains("sys.inputs"))
+}
+
+/// Whether the Typst file, any of its dependencies, its `sys.inputs` or the preamble
+/// applied to it has been modified since it was last compiled to `output_path`, like
+/// [`config::verify_and_file_hash`]. The dependencies are recorded for the source
+/// being parsed, see [`config::record_dependencies`].
+pub fn verify_file_dependencies(
+    typst_path: &str,
+    output_path: &str,
+    inputs: &Inputs,
+    preamble: &str,
+) -> Result<bool, std::io::Error> {
+    let mut dependencies = file_dependencies(typst_path);
+    // The paths of the preamble are absolute, see [`rebase`].
+    dependencies.extend(source_dependencies(preamble, ""));
+    let hash = dependencies_hash(&dependencies);
+    config::record_dependencies(dependencies);
+    let hash = format!("{:016x}\0{:?}\0{}", hash, inputs, preamble);
+    let hash = config::stable_hash(hash.as_bytes());
+    let output_path = config::relative_to_root(output_path);
+    config::verify_update_hash(&output_path, &format!("{:016x}", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_lexically() {
+        assert_eq!(normalize(Path::new("./a/b/../c.typ")), "a/c.typ");
+        assert_eq!(normalize(Path::new("/a/./b.typ")), "a/b.typ");
+        assert_eq!(normalize(Path::new("../../a.typ")), "../../a.typ");
+        assert_eq!(normalize(Path::new("a/../../b.typ")), "../b.typ");
+    }
+
+    #[test]
+    fn scan_the_imported_and_read_files() {
+        let src = r#"
+            #import "/lib/util.typ": *
+            #include "chapter.typ"
+            #import "@preview/cetz:0.3.0"
+            #image( "../fig/plot.png", width: 50%)
+            #let data = csv("data.csv")
+            #let name = read(path)
+        "#;
+        assert_eq!(
+            scan(src, Path::new("notes/topic")),
+            [
+                "lib/util.typ",
+                "notes/topic/chapter.typ",
+                "notes/fig/plot.png",
+                "notes/topic/data.csv",
+            ]
+        );
+    }
+
+    #[test]
+    fn rebase_the_relative_paths() {
+        let src = "#import \"util.typ\": f\n#image(\"../plot.svg\")\n#include \"/abs.typ\"\n#import \"@preview/cetz:0.3.0\"";
+        assert_eq!(
+            rebase(src, Path::new("notes/topic")),
+            "#import \"/notes/topic/util.typ\": f\n#image(\"/notes/plot.svg\")\n#include \"/abs.typ\"\n#import \"@preview/cetz:0.3.0\""
+        );
+    }
+}
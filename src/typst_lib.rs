@@ -0,0 +1,313 @@
+//! Typst linked as a library with the `embed-typst` feature, to compile the SVGs and the
+//! HTML without spawning a `typst` process for each of them. The [`KodamaWorld`] lives as
+//! long as the build, so the fonts are loaded once and the comemo caches are shared by all
+//! the formulas, figures and `.typst` entries. Only the documents which import a package
+//! which is not downloaded yet fall back to the `typst` CLI, which downloads it. The fonts
+//! and packages follow `[typst]` in `kodama.toml`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex, OnceLock,
+    },
+};
+
+use typst::{
+    diag::{FileError, FileResult, PackageError, Severity, SourceDiagnostic, SourceResult},
+    ecow::eco_vec,
+    foundations::{Bytes, Datetime, Dict, Value},
+    html::HtmlDocument,
+    layout::PagedDocument,
+    syntax::{package::PackageSpec, FileId, Source, Span, VirtualPath},
+    text::{Font, FontBook, FontInfo},
+    utils::LazyHash,
+    Document, Feature, Library, LibraryBuilder, World,
+};
+
+use crate::{config, typst_cli::Inputs, typst_deps};
+
+static WORLD: LazyLock<Mutex<KodamaWorld>> = LazyLock::new(|| Mutex::new(KodamaWorld::new()));
+
+/// The main file of a compilation, relative to the project root.
+pub enum Main<'a> {
+    File(&'a str),
+
+    /// A source which is not written to the disk, as if it were the file.
+    Source(&'a str, &'a str),
+}
+
+/// Compile the document to the SVGs of its pages, and print the warnings if `report`.
+/// `None` if the document has to be compiled by the CLI, i.e. it imports a package
+/// which is not downloaded, otherwise the errors if it fails, see [`print_errors`].
+pub fn compile_svgs(
+    main: Main,
+    inputs: &Inputs,
+    report: bool,
+) -> Option<SourceResult<Vec<String>>> {
+    let result = compile::<PagedDocument>(main, inputs, report)?;
+    Some(result.map(|document| document.pages.iter().map(typst_svg::svg).collect()))
+}
+
+/// Compile the document to HTML like [`compile_svgs`], printing the warnings.
+pub fn compile_html(main: Main, inputs: &Inputs) -> Option<SourceResult<String>> {
+    let result = compile::<HtmlDocument>(main, inputs, true)?;
+    Some(result.and_then(|document| typst_html::html(&document)))
+}
+
+fn compile<D: Document>(main: Main, inputs: &Inputs, report: bool) -> Option<SourceResult<D>> {
+    let mut world = WORLD.lock().unwrap();
+    world.set_inputs(inputs);
+    if let Err(err) = world.set_main(main) {
+        return Some(Err(eco_vec![SourceDiagnostic::error(
+            Span::detached(),
+            err.to_string()
+        )]));
+    }
+
+    let result = typst::compile::<D>(&*world);
+    comemo::evict(10);
+    if world.missing_package.swap(false, Ordering::Relaxed) {
+        return None;
+    }
+    if report {
+        print_diagnostics(&world, &result.warnings);
+    }
+    Some(result.output)
+}
+
+/// Print the errors of a failed compilation with their positions.
+pub fn print_errors(errors: &[SourceDiagnostic]) {
+    print_diagnostics(&WORLD.lock().unwrap(), errors);
+}
+
+fn print_diagnostics(world: &KodamaWorld, diagnostics: &[SourceDiagnostic]) {
+    for diagnostic in diagnostics {
+        let severity = match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        eprintln!(
+            "Typst {} in {}: {}",
+            severity,
+            world.position(diagnostic),
+            diagnostic.message
+        );
+        for hint in &diagnostic.hints {
+            eprintln!("  hint: {}", hint);
+        }
+    }
+}
+
+struct FontSlot {
+    path: PathBuf,
+    index: u32,
+    font: OnceLock<Option<Font>>,
+}
+
+struct KodamaWorld {
+    root: PathBuf,
+    library: LazyHash<Library>,
+
+    /// The `sys.inputs` of the library.
+    inputs: Inputs,
+    book: LazyHash<FontBook>,
+
+    /// The embedded fonts of Typst, and then the system fonts loaded on demand.
+    embedded: Vec<Font>,
+    fonts: Vec<FontSlot>,
+
+    /// The parsed sources, which are edited incrementally when the files change.
+    sources: Mutex<HashMap<FileId, Source>>,
+    main: Option<Source>,
+    missing_package: AtomicBool,
+}
+
+impl KodamaWorld {
+    fn new() -> KodamaWorld {
+        let embedded: Vec<Font> = typst_assets::fonts()
+            .flat_map(|data| Font::iter(Bytes::new(data)))
+            .collect();
+        let mut book = FontBook::from_fonts(&embedded);
+
+        let typst = &config::project_config().typst;
+        let root_dir = config::root_dir();
+        let mut database = fontdb::Database::new();
+        for font_path in &typst.font_paths {
+            database.load_fonts_dir(config::join_path(&root_dir, font_path));
+        }
+        if !typst.ignore_system_fonts {
+            database.load_system_fonts();
+        }
+        let mut fonts = vec![];
+        for face in database.faces() {
+            let path = match &face.source {
+                fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => path,
+                fontdb::Source::Binary(_) => continue,
+            };
+            let info = database.with_face_data(face.id, FontInfo::new);
+            if let Some(info) = info.flatten() {
+                book.push(info);
+                fonts.push(FontSlot {
+                    path: path.clone(),
+                    index: face.index,
+                    font: OnceLock::new(),
+                });
+            }
+        }
+
+        KodamaWorld {
+            root: PathBuf::from(root_dir),
+            library: LazyHash::new(library_builder().build()),
+            inputs: vec![],
+            book: LazyHash::new(book),
+            embedded,
+            fonts,
+            sources: Mutex::new(HashMap::new()),
+            main: None,
+            missing_package: AtomicBool::new(false),
+        }
+    }
+
+    fn set_inputs(&mut self, inputs: &Inputs) {
+        if self.inputs != *inputs {
+            let dict: Dict = inputs
+                .iter()
+                .map(|(key, value)| ((*key).into(), Value::Str(value.as_str().into())))
+                .collect();
+            self.library = LazyHash::new(library_builder().with_inputs(dict).build());
+            self.inputs = inputs.clone();
+        }
+    }
+
+    fn set_main(&mut self, main: Main) -> FileResult<()> {
+        let (path, text) = match main {
+            Main::File(path) => (path, None),
+            Main::Source(path, text) => (path, Some(text)),
+        };
+        let id = FileId::new(None, VirtualPath::new(path));
+        self.main = None;
+        let source = match text {
+            Some(text) => self.update_source(id, text.to_string()),
+            None => self.source(id)?,
+        };
+        self.main = Some(source);
+        Ok(())
+    }
+
+    fn path(&self, id: FileId) -> FileResult<PathBuf> {
+        let root = match id.package() {
+            Some(package) => match package_dir(package) {
+                Some(dir) => dir,
+                None => {
+                    self.missing_package.store(true, Ordering::Relaxed);
+                    return Err(FileError::Package(PackageError::NotFound(package.clone())));
+                }
+            },
+            None => self.root.clone(),
+        };
+        id.vpath().resolve(&root).ok_or(FileError::AccessDenied)
+    }
+
+    fn update_source(&self, id: FileId, text: String) -> Source {
+        let mut sources = self.sources.lock().unwrap();
+        match sources.get_mut(&id) {
+            Some(source) => {
+                if source.text() != text {
+                    source.replace(&text);
+                }
+                source.clone()
+            }
+            None => {
+                let source = Source::new(id, text);
+                sources.insert(id, source.clone());
+                source
+            }
+        }
+    }
+
+    /// `path:line:column` of the diagnostic, as far as it is known.
+    fn position(&self, diagnostic: &SourceDiagnostic) -> String {
+        let Some(id) = diagnostic.span.id() else {
+            return "<unknown>".to_string();
+        };
+        let path = id.vpath().as_rootless_path().display().to_string();
+        let Ok(source) = self.source(id) else {
+            return path;
+        };
+        match source.range(diagnostic.span) {
+            Some(range) => format!(
+                "{}:{}:{}",
+                path,
+                source.byte_to_line(range.start).map_or(0, |line| line + 1),
+                source
+                    .byte_to_column(range.start)
+                    .map_or(0, |column| column + 1)
+            ),
+            None => path,
+        }
+    }
+}
+
+impl World for KodamaWorld {
+    fn library(&self) -> &LazyHash<Library> {
+        &self.library
+    }
+
+    fn book(&self) -> &LazyHash<FontBook> {
+        &self.book
+    }
+
+    fn main(&self) -> FileId {
+        let main = self.main.as_ref().expect(concat!(file!(), '#', line!()));
+        main.id()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        if let Some(main) = self.main.as_ref().filter(|main| main.id() == id) {
+            return Ok(main.clone());
+        }
+        let path = self.path(id)?;
+        let text = fs::read_to_string(&path).map_err(|err| FileError::from_io(err, &path))?;
+        Ok(self.update_source(id, text))
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        let path = self.path(id)?;
+        fs::read(&path)
+            .map(Bytes::new)
+            .map_err(|err| FileError::from_io(err, &path))
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        if let Some(font) = self.embedded.get(index) {
+            return Some(font.clone());
+        }
+        let slot = self.fonts.get(index - self.embedded.len())?;
+        slot.font
+            .get_or_init(|| {
+                let data = fs::read(&slot.path).ok()?;
+                Font::new(Bytes::new(data), slot.index)
+            })
+            .clone()
+    }
+
+    fn today(&self, _offset: Option<i64>) -> Option<Datetime> {
+        let today = crate::date::Date::today();
+        Datetime::from_ymd(today.year as i32, today.month?, today.day?)
+    }
+}
+
+/// The standard library with the HTML export, as `--features=html` of the CLI.
+fn library_builder() -> LibraryBuilder {
+    Library::builder().with_features([Feature::Html].into_iter().collect())
+}
+
+/// The directory of the package, see [`typst_deps::package_dir`].
+fn package_dir(package: &PackageSpec) -> Option<PathBuf> {
+    let version = package.version.to_string();
+    let dir = typst_deps::package_dir(&package.namespace, &package.name, &version)?;
+    Some(PathBuf::from(config::join_path(&config::root_dir(), &dir)))
+}
@@ -16,9 +16,30 @@
   html.elem("kodamameta", v, attrs: attrs)
 }
 
-#let embed(url, title, numbering: false, open: true, catalog: true) = {
+#let embed(
+  url,
+  title,
+  numbering: false,
+  open: true,
+  catalog: true,
+  header: true,
+  metadata: true,
+  card: false,
+  preview: none,
+  taxon: none,
+) = {
   let v = title
-  let attrs = (url: url, numbering: repri(numbering), open: repri(open), catalog: repri(catalog))
+  let attrs = (
+    url: url,
+    numbering: repri(numbering),
+    open: repri(open),
+    catalog: repri(catalog),
+    header: repri(header),
+    metadata: repri(metadata),
+    card: repri(card),
+    preview: repri(preview),
+    taxon: repri(taxon),
+  )
 
   if type(title) != content {
     v = none
@@ -28,6 +49,37 @@
   html.elem("kodamaembed", v, attrs: attrs)
 }
 
+/// Lists (or embeds with `mode: "embed"`) the entries matching all filters.
+/// `meta` is a dictionary of metadata to match, and the rest named arguments
+/// are options of each embedded entry, as in `embed`.
+#let query-entries(
+  taxon: none,
+  tag: none,
+  under: none,
+  meta: (:),
+  sort: "title",
+  limit: none,
+  mode: "list",
+  ..options,
+) = {
+  let params = (taxon: taxon, tag: tag, under: under, sort: sort, limit: limit, mode: mode)
+  for (key, value) in meta {
+    params.insert("meta." + key, value)
+  }
+  let query = params
+    .pairs()
+    .filter(((key, value)) => value != none)
+    .map(((key, value)) => key + "=" + repri(value))
+    .join("&")
+
+  let attrs = (query: query)
+  for (key, value) in options.named() {
+    attrs.insert(key, repri(value))
+  }
+
+  html.elem("kodamaquery", attrs: attrs)
+}
+
 #let local(slug, text) = html.elem(
   "span", // Make it an inline element. This is automatically removed by kodama.
   {
@@ -43,6 +95,14 @@
   },
 )
 
+/// Cites the keys of the bibliography, e.g. `cite("knuth1984", "lamport1994")`,
+/// as links to the generated reference entries.
+#let cite(..keys) = {
+  "["
+  keys.pos().map(key => local("bib/" + key.replace(regex("[^\w-]"), "-"), "")).join("; ")
+  "]"
+}
+
 #let template(it) = {
   show: html.elem.with("html")
 